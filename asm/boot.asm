@@ -51,6 +51,11 @@ extern start64
 _start:
     ; Set up stack
     mov esp, stack_top
+
+    ; Keep the Multiboot magic and info pointer for start64 (System V
+    ; arguments), nothing below touches edi and esi
+    mov edi, eax
+    mov esi, ebx
     
     ; Set up page tables for 64-bit mode
     ; Map first P4 entry to P3 table
@@ -112,7 +117,11 @@ long_mode_start:
     mov es, ax
     mov fs, ax
     mov gs, ax
-    
+
+    ; The upper halves are undefined after the switch, clear them
+    mov edi, edi
+    mov esi, esi
+
     ; Call Rust entry point
     call start64
     
@@ -13,17 +13,18 @@ isr%1:
 ; Macro for exceptions WITH error code
 %macro ISR_ERRCODE 1
 global isr%1
+%define ERRCODE_%1
 isr%1:
     push qword %1         ; Push interrupt number (error code already pushed by CPU)
     jmp isr_common_stub
 %endmacro
 
-; Macro for IRQs (hardware interrupts)
-%macro IRQ 2
-global irq%1
-irq%1:
+; Macro for every other vector (hardware IRQs, MSIs, IPIs, software interrupts)
+%macro VECTOR 1
+global vector%1
+vector%1:
     push qword 0          ; Dummy error code
-    push qword %2         ; Push IRQ number (32 + IRQ number)
+    push qword %1         ; Push vector number
     jmp irq_common_stub
 %endmacro
 
@@ -49,7 +50,7 @@ ISR_ERRCODE   17   ; Alignment check (has error code)
 ISR_NOERRCODE 18   ; Machine check
 ISR_NOERRCODE 19   ; SIMD floating-point exception
 ISR_NOERRCODE 20   ; Virtualization exception
-ISR_NOERRCODE 21   ; Reserved
+ISR_ERRCODE   21   ; Control protection (has error code)
 ISR_NOERRCODE 22   ; Reserved
 ISR_NOERRCODE 23   ; Reserved
 ISR_NOERRCODE 24   ; Reserved
@@ -57,27 +58,50 @@ ISR_NOERRCODE 25   ; Reserved
 ISR_NOERRCODE 26   ; Reserved
 ISR_NOERRCODE 27   ; Reserved
 ISR_NOERRCODE 28   ; Reserved
-ISR_NOERRCODE 29   ; Reserved
+ISR_ERRCODE   29   ; VMM communication (has error code)
 ISR_ERRCODE   30   ; Security exception (has error code)
 ISR_NOERRCODE 31   ; Reserved
 
-; Hardware IRQ handlers (32-47)
-IRQ 0, 32    ; Timer
-IRQ 1, 33    ; Keyboard
-IRQ 2, 34    ; Cascade
-IRQ 3, 35    ; COM2
-IRQ 4, 36    ; COM1
-IRQ 5, 37    ; LPT2
-IRQ 6, 38    ; Floppy
-IRQ 7, 39    ; LPT1
-IRQ 8, 40    ; CMOS RTC
-IRQ 9, 41    ; Free
-IRQ 10, 42   ; Free
-IRQ 11, 43   ; Free
-IRQ 12, 44   ; PS/2 Mouse
-IRQ 13, 45   ; FPU
-IRQ 14, 46   ; Primary ATA
-IRQ 15, 47   ; Secondary ATA
+; Interrupt vectors (32-255): ISA IRQs on 32-47, the rest is allocated at runtime
+%assign i 32
+%rep 224
+VECTOR i
+%assign i i+1
+%endrep
+
+; Entry stubs indexed by vector number, split by the kind of stub: a vector
+; has its stub in one table and a null entry in the other
+section .rodata
+global error_code_stub_table
+error_code_stub_table:
+%assign i 0
+%rep 32
+%ifdef ERRCODE_%[i]
+    dq isr%+i
+%else
+    dq 0
+%endif
+%assign i i+1
+%endrep
+    times 224 dq 0
+
+global no_error_code_stub_table
+no_error_code_stub_table:
+%assign i 0
+%rep 32
+%ifdef ERRCODE_%[i]
+    dq 0
+%else
+    dq isr%+i
+%endif
+%assign i i+1
+%endrep
+%rep 224
+    dq vector%+i
+%assign i i+1
+%endrep
+
+section .text
 
 ; Common stub for CPU exceptions
 extern rust_exception_handler
@@ -172,16 +196,3 @@ irq_common_stub:
     
     ; Return from interrupt
     iretq
-
-; Export individual handlers for IDT
-global divide_by_zero_handler
-global general_protection_fault_handler
-global page_fault_handler
-global timer_handler
-global keyboard_handler
-
-divide_by_zero_handler equ isr0
-general_protection_fault_handler equ isr13
-page_fault_handler equ isr14
-timer_handler equ irq0
-keyboard_handler equ irq1
@@ -0,0 +1,16 @@
+use super::{find_table, AcpiError, SdtHeader};
+
+// Offset of the CMOS century register index in the FADT
+const CENTURY_OFFSET: usize = 108;
+
+/// CMOS register holding the RTC century, if the firmware has one.
+pub fn century_register() -> Result<Option<u8>, AcpiError> {
+    let header = find_table(b"FACP")?;
+    if header.length as usize <= CENTURY_OFFSET {
+        return Ok(None);
+    }
+
+    let base = header as *const SdtHeader as *const u8;
+    let index = unsafe { *base.add(CENTURY_OFFSET) };
+    Ok((index != 0).then_some(index))
+}
@@ -0,0 +1,47 @@
+use core::ptr::read_unaligned;
+
+use super::{find_table, AcpiError, SdtHeader};
+
+// Generic Address Structure space id of system memory
+const ADDRESS_SPACE_MEMORY: u8 = 0;
+
+/// Contents of the HPET Description Table ("HPET").
+#[derive(Clone, Copy, Debug)]
+pub struct Hpet {
+    /// Physical address of the register block
+    pub address: u64,
+    pub number: u8,
+    /// Smallest periodic tick the firmware guarantees, in counter ticks
+    pub min_tick: u16,
+}
+
+// Offsets in the table, after the standard header and the block id
+const ADDRESS_SPACE_OFFSET: usize = 40;
+const ADDRESS_OFFSET: usize = 44;
+const NUMBER_OFFSET: usize = 52;
+const MIN_TICK_OFFSET: usize = 53;
+const TABLE_LENGTH: usize = 56;
+
+/// Parses the HPET Description Table.
+pub fn parse() -> Result<Hpet, AcpiError> {
+    let header = find_table(b"HPET")?;
+    if (header.length as usize) < TABLE_LENGTH {
+        return Err(AcpiError::TableNotFound);
+    }
+
+    let base = header as *const SdtHeader as *const u8;
+    let read_at = |offset: usize| unsafe { base.add(offset) };
+    let (space, address, number, min_tick) = unsafe {
+        (
+            *read_at(ADDRESS_SPACE_OFFSET),
+            read_unaligned(read_at(ADDRESS_OFFSET) as *const u64),
+            *read_at(NUMBER_OFFSET),
+            read_unaligned(read_at(MIN_TICK_OFFSET) as *const u16),
+        )
+    };
+
+    if space != ADDRESS_SPACE_MEMORY || address == 0 {
+        return Err(AcpiError::TableNotFound);
+    }
+    Ok(Hpet { address, number, min_tick })
+}
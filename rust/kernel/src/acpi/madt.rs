@@ -0,0 +1,196 @@
+use core::mem::size_of;
+use core::ptr::read_unaligned;
+
+use super::{find_table, AcpiError, SdtHeader};
+
+pub const MAX_CPUS: usize = 16;
+pub const MAX_IO_APICS: usize = 4;
+pub const MAX_OVERRIDES: usize = 16;
+pub const MAX_LAPIC_NMIS: usize = 4;
+
+// Processor flags
+const CPU_ENABLED: u32 = 1 << 0;
+const CPU_ONLINE_CAPABLE: u32 = 1 << 1;
+
+// MPS INTI flags, used by overrides and NMI entries
+pub const INTI_POLARITY_MASK: u16 = 0b11;
+pub const INTI_ACTIVE_LOW: u16 = 0b11;
+pub const INTI_TRIGGER_MASK: u16 = 0b11 << 2;
+pub const INTI_LEVEL_TRIGGERED: u16 = 0b11 << 2;
+
+// MADT flags: the system also has a pair of 8259 PICs
+const PCAT_COMPAT: u32 = 1 << 0;
+
+/// Processor ID of NMI entries that apply to every CPU. The MADT uses 0xFF in
+/// local APIC entries and 0xFFFFFFFF in x2APIC ones.
+pub const ALL_PROCESSORS: u32 = u32::MAX;
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct LocalApicEntry {
+    pub processor_id: u32,
+    pub apic_id: u32,
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct IoApicEntry {
+    pub id: u8,
+    pub address: u32,
+    pub gsi_base: u32,
+}
+
+/// Remaps an ISA IRQ to a different global system interrupt, possibly with a
+/// non-default polarity or trigger mode (MPS INTI flags).
+#[derive(Clone, Copy, Default, Debug)]
+pub struct InterruptOverride {
+    pub source: u8,
+    pub gsi: u32,
+    pub flags: u16,
+}
+
+/// LINT pin of a local APIC wired to NMI, on every CPU when `processor_id` is
+/// `ALL_PROCESSORS`.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct LocalApicNmi {
+    pub processor_id: u32,
+    pub flags: u16,
+    pub lint: u8,
+}
+
+/// The interrupt controller layout described by the MADT.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Madt {
+    pub local_apic_address: u64,
+    pub has_8259: bool,
+    pub cpus: [LocalApicEntry; MAX_CPUS],
+    pub cpu_count: usize,
+    pub io_apics: [IoApicEntry; MAX_IO_APICS],
+    pub io_apic_count: usize,
+    pub overrides: [InterruptOverride; MAX_OVERRIDES],
+    pub override_count: usize,
+    pub nmis: [LocalApicNmi; MAX_LAPIC_NMIS],
+    pub nmi_count: usize,
+}
+
+impl Madt {
+    pub fn cpus(&self) -> &[LocalApicEntry] {
+        &self.cpus[..self.cpu_count]
+    }
+
+    pub fn io_apics(&self) -> &[IoApicEntry] {
+        &self.io_apics[..self.io_apic_count]
+    }
+
+    pub fn overrides(&self) -> &[InterruptOverride] {
+        &self.overrides[..self.override_count]
+    }
+
+    pub fn nmis(&self) -> &[LocalApicNmi] {
+        &self.nmis[..self.nmi_count]
+    }
+
+    /// Returns the override for an ISA IRQ, if the firmware declared one.
+    pub fn isa_override(&self, irq: u8) -> Option<&InterruptOverride> {
+        self.overrides().iter().find(|o| o.source == irq)
+    }
+}
+
+/// Parses the Multiple APIC Description Table ("APIC").
+pub fn parse() -> Result<Madt, AcpiError> {
+    let header = find_table(b"APIC")?;
+    let base = header as *const SdtHeader as *const u8;
+    let length = header.length as usize;
+
+    let mut madt = Madt::default();
+
+    // Local APIC address and flags follow the standard header
+    let mut offset = size_of::<SdtHeader>();
+    madt.local_apic_address = read::<u32>(base, offset) as u64;
+    madt.has_8259 = read::<u32>(base, offset + 4) & PCAT_COMPAT != 0;
+    offset += 8;
+
+    while offset + 2 <= length {
+        let entry_type = read::<u8>(base, offset);
+        let entry_length = read::<u8>(base, offset + 1) as usize;
+        if entry_length < 2 || offset + entry_length > length {
+            break;
+        }
+
+        match entry_type {
+            // Processor local APIC
+            0 => {
+                let flags = read::<u32>(base, offset + 4);
+                if flags & (CPU_ENABLED | CPU_ONLINE_CAPABLE) != 0 && madt.cpu_count < MAX_CPUS {
+                    madt.cpus[madt.cpu_count] = LocalApicEntry {
+                        processor_id: read::<u8>(base, offset + 2) as u32,
+                        apic_id: read::<u8>(base, offset + 3) as u32,
+                    };
+                    madt.cpu_count += 1;
+                }
+            }
+            // I/O APIC
+            1 if madt.io_apic_count < MAX_IO_APICS => {
+                madt.io_apics[madt.io_apic_count] = IoApicEntry {
+                    id: read::<u8>(base, offset + 2),
+                    address: read::<u32>(base, offset + 4),
+                    gsi_base: read::<u32>(base, offset + 8),
+                };
+                madt.io_apic_count += 1;
+            }
+            // Interrupt source override
+            2 if madt.override_count < MAX_OVERRIDES => {
+                madt.overrides[madt.override_count] = InterruptOverride {
+                    source: read::<u8>(base, offset + 3),
+                    gsi: read::<u32>(base, offset + 4),
+                    flags: read::<u16>(base, offset + 8),
+                };
+                madt.override_count += 1;
+            }
+            // Local APIC NMI
+            4 if madt.nmi_count < MAX_LAPIC_NMIS => {
+                let processor_id = match read::<u8>(base, offset + 2) {
+                    0xFF => ALL_PROCESSORS,
+                    id => id as u32,
+                };
+                madt.nmis[madt.nmi_count] = LocalApicNmi {
+                    processor_id,
+                    flags: read::<u16>(base, offset + 3),
+                    lint: read::<u8>(base, offset + 5),
+                };
+                madt.nmi_count += 1;
+            }
+            // Local APIC address override
+            5 => {
+                madt.local_apic_address = read::<u64>(base, offset + 4);
+            }
+            // Processor local x2APIC
+            9 => {
+                let flags = read::<u32>(base, offset + 8);
+                if flags & (CPU_ENABLED | CPU_ONLINE_CAPABLE) != 0 && madt.cpu_count < MAX_CPUS {
+                    madt.cpus[madt.cpu_count] = LocalApicEntry {
+                        processor_id: read::<u32>(base, offset + 12),
+                        apic_id: read::<u32>(base, offset + 4),
+                    };
+                    madt.cpu_count += 1;
+                }
+            }
+            // Local x2APIC NMI
+            0xA if madt.nmi_count < MAX_LAPIC_NMIS => {
+                madt.nmis[madt.nmi_count] = LocalApicNmi {
+                    processor_id: read::<u32>(base, offset + 4),
+                    flags: read::<u16>(base, offset + 2),
+                    lint: read::<u8>(base, offset + 8),
+                };
+                madt.nmi_count += 1;
+            }
+            _ => {}
+        }
+
+        offset += entry_length;
+    }
+
+    Ok(madt)
+}
+
+fn read<T: Copy>(base: *const u8, offset: usize) -> T {
+    unsafe { read_unaligned(base.add(offset) as *const T) }
+}
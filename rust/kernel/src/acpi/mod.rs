@@ -0,0 +1,142 @@
+pub mod fadt;
+pub mod hpet;
+pub mod madt;
+
+use core::mem::size_of;
+use core::ptr::read_unaligned;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::paging::map_memory;
+
+/// Header shared by every ACPI system description table.
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32,
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct Rsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+    // Fields below are only valid for revision >= 2
+    length: u32,
+    xsdt_address: u64,
+    extended_checksum: u8,
+    reserved: [u8; 3],
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum AcpiError {
+    RsdpNotFound,
+    InvalidChecksum,
+    TableNotFound,
+}
+
+// Physical address of the RSDT/XSDT, zero until `init` succeeds
+static ROOT_TABLE: AtomicU64 = AtomicU64::new(0);
+static ROOT_IS_XSDT: AtomicBool = AtomicBool::new(false);
+
+/// Looks for the RSDP in the BIOS areas and remembers the root table.
+pub fn init() -> Result<(), AcpiError> {
+    let rsdp = find_rsdp().ok_or(AcpiError::RsdpNotFound)?;
+    let rsdp = unsafe { read_unaligned(rsdp) };
+
+    let (root, is_xsdt) = if rsdp.revision >= 2 && rsdp.xsdt_address != 0 {
+        (rsdp.xsdt_address, true)
+    } else {
+        (rsdp.rsdt_address as u64, false)
+    };
+
+    let header = unsafe { map_table(root) };
+    if !checksum_ok(header as *const u8, unsafe { (*header).length } as usize) {
+        return Err(AcpiError::InvalidChecksum);
+    }
+
+    ROOT_IS_XSDT.store(is_xsdt, Ordering::Relaxed);
+    ROOT_TABLE.store(root, Ordering::Release);
+    Ok(())
+}
+
+/// Returns the table with the given signature (e.g. `b"APIC"`).
+pub fn find_table(signature: &[u8; 4]) -> Result<&'static SdtHeader, AcpiError> {
+    let root = ROOT_TABLE.load(Ordering::Acquire);
+    if root == 0 {
+        return Err(AcpiError::TableNotFound);
+    }
+
+    let is_xsdt = ROOT_IS_XSDT.load(Ordering::Relaxed);
+    let entry_size = if is_xsdt { 8 } else { 4 };
+
+    let header = unsafe { map_table(root) };
+    let length = unsafe { (*header).length } as usize;
+    let entries = (length - size_of::<SdtHeader>()) / entry_size;
+    let first_entry = unsafe { (header as *const u8).add(size_of::<SdtHeader>()) };
+
+    for i in 0..entries {
+        let address = unsafe {
+            let entry = first_entry.add(i * entry_size);
+            if is_xsdt {
+                read_unaligned(entry as *const u64)
+            } else {
+                read_unaligned(entry as *const u32) as u64
+            }
+        };
+
+        let table = unsafe { map_table(address) };
+        let table = unsafe { &*table };
+        if &table.signature == signature {
+            if !checksum_ok(table as *const _ as *const u8, table.length as usize) {
+                return Err(AcpiError::InvalidChecksum);
+            }
+            return Ok(table);
+        }
+    }
+
+    Err(AcpiError::TableNotFound)
+}
+
+/// Makes sure the whole table at `address` is mapped before it is read.
+unsafe fn map_table(address: u64) -> *const SdtHeader {
+    unsafe {
+        map_memory(address, size_of::<SdtHeader>() as u64);
+        let length = read_unaligned(address as *const SdtHeader).length;
+        map_memory(address, length as u64) as *const SdtHeader
+    }
+}
+
+fn find_rsdp() -> Option<*const Rsdp> {
+    // The first KiB of the Extended BIOS Data Area, whose segment is stored at 0x40E
+    let ebda = (unsafe { read_unaligned(0x40E as *const u16) } as usize) << 4;
+    if ebda != 0 && let Some(rsdp) = scan_rsdp(ebda, ebda + 1024) {
+        return Some(rsdp);
+    }
+
+    // The main BIOS area
+    scan_rsdp(0xE0000, 0x100000)
+}
+
+fn scan_rsdp(start: usize, end: usize) -> Option<*const Rsdp> {
+    (start..end).step_by(16).map(|addr| addr as *const Rsdp).find(|&rsdp| {
+        let signature = unsafe { read_unaligned(rsdp).signature };
+        // The ACPI 1.0 part is always 20 bytes long
+        &signature == b"RSD PTR " && checksum_ok(rsdp as *const u8, 20)
+    })
+}
+
+fn checksum_ok(table: *const u8, length: usize) -> bool {
+    let bytes = unsafe { core::slice::from_raw_parts(table, length) };
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+}
@@ -0,0 +1,38 @@
+use core::fmt;
+
+use crate::uaccess::probe_read;
+
+const MAX_FRAMES: usize = 16;
+
+/// Walks the frame pointer chain starting at `rbp` and writes one return
+/// address per line, `rip` first. Needs the kernel to be built with frame
+/// pointers; reads go through `probe_read`, so a broken chain only ends the
+/// walk early.
+pub fn write_backtrace(w: &mut impl fmt::Write, rip: u64, mut rbp: u64) -> fmt::Result {
+    writeln!(w, "Backtrace:")?;
+    writeln!(w, "  #0  {:#018x}", rip)?;
+
+    for depth in 1..MAX_FRAMES {
+        // The saved frame pointer and the return address sit at [rbp] and [rbp + 8]
+        if rbp == 0 || !rbp.is_multiple_of(8) {
+            break;
+        }
+        let next = probe_read(rbp as *const u64);
+        let ret = probe_read((rbp + 8) as *const u64);
+        let (Ok(next), Ok(ret)) = (next, ret) else {
+            break;
+        };
+        if ret == 0 {
+            break;
+        }
+
+        writeln!(w, "  #{:<2} {:#018x}", depth, ret)?;
+
+        // Frames grow down, the caller's frame must be above ours
+        if next <= rbp {
+            break;
+        }
+        rbp = next;
+    }
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use core::arch::asm;
+use core::arch::x86_64::{CpuidResult, __cpuid};
+
+pub const IA32_APIC_BASE: u32 = 0x1B;
+
+// Interrupt flag in RFLAGS
+const RFLAGS_IF: u64 = 1 << 9;
+
+pub fn cpuid(leaf: u32) -> CpuidResult {
+    __cpuid(leaf)
+}
+
+pub unsafe fn rdmsr(msr: u32) -> u64 {
+    let (low, high): (u32, u32);
+    unsafe {
+        asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags));
+    }
+    ((high as u64) << 32) | low as u64
+}
+
+pub unsafe fn wrmsr(msr: u32, value: u64) {
+    unsafe {
+        asm!("wrmsr", in("ecx") msr, in("eax") value as u32, in("edx") (value >> 32) as u32, options(nostack, preserves_flags));
+    }
+}
+
+pub fn read_cr3() -> u64 {
+    let value: u64;
+    unsafe {
+        asm!("mov {}, cr3", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+pub unsafe fn invlpg(addr: u64) {
+    unsafe {
+        asm!("invlpg [{}]", in(reg) addr, options(nostack, preserves_flags));
+    }
+}
+
+pub fn rdtsc() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// Disables interrupts, returning whether they were enabled.
+pub fn disable_interrupts() -> bool {
+    let rflags: u64;
+    unsafe {
+        asm!("pushfq", "pop {}", "cli", out(reg) rflags);
+    }
+    rflags & RFLAGS_IF != 0
+}
+
+/// Re-enables interrupts if `enabled`, the value returned by `disable_interrupts`.
+pub fn restore_interrupts(enabled: bool) {
+    if enabled {
+        unsafe {
+            asm!("sti", options(nostack));
+        }
+    }
+}
+
+pub fn interrupts_enabled() -> bool {
+    let rflags: u64;
+    unsafe {
+        asm!("pushfq", "pop {}", out(reg) rflags, options(nomem, preserves_flags));
+    }
+    rflags & RFLAGS_IF != 0
+}
@@ -0,0 +1,121 @@
+use core::arch::asm;
+
+use crate::uaccess::{probe_read, probe_write, AccessError};
+
+pub const MAX_BREAKPOINTS: usize = 8;
+pub const MAX_WATCHPOINTS: usize = 4;
+
+const INT3: u8 = 0xCC;
+
+// DR6 status bits
+pub const DR6_HIT_MASK: u64 = 0b1111;
+pub const DR6_SINGLE_STEP: u64 = 1 << 14;
+
+/// An `int3` patched over the first byte of an instruction.
+#[derive(Clone, Copy)]
+pub struct Breakpoint {
+    pub address: u64,
+    original: u8,
+    // False while the original byte is in place, e.g. while stepping over it
+    pub inserted: bool,
+}
+
+impl Breakpoint {
+    pub fn new(address: u64) -> Result<Breakpoint, AccessError> {
+        let original = probe_read(address as *const u8)?;
+        Ok(Breakpoint { address, original, inserted: false })
+    }
+
+    pub fn insert(&mut self) -> Result<(), AccessError> {
+        if !self.inserted {
+            unsafe { probe_write(self.address as *mut u8, INT3)? };
+            self.inserted = true;
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self) -> Result<(), AccessError> {
+        if self.inserted {
+            unsafe { probe_write(self.address as *mut u8, self.original)? };
+            self.inserted = false;
+        }
+        Ok(())
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum WatchKind {
+    Execute,
+    Write,
+    // The CPU cannot trap reads alone, this also triggers on writes
+    ReadWrite,
+}
+
+impl WatchKind {
+    fn rw_bits(self) -> u64 {
+        match self {
+            WatchKind::Execute => 0b00,
+            WatchKind::Write => 0b01,
+            WatchKind::ReadWrite => 0b11,
+        }
+    }
+}
+
+/// A hardware watchpoint held in one of DR0-DR3.
+#[derive(Clone, Copy)]
+pub struct Watchpoint {
+    pub address: u64,
+    pub len: u8,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    /// Length field of DR7: 1, 2, 4 or 8 bytes, execution watchpoints are always 1.
+    fn len_bits(&self) -> u64 {
+        match (self.kind, self.len) {
+            (WatchKind::Execute, _) | (_, 1) => 0b00,
+            (_, 2) => 0b01,
+            (_, 8) => 0b10,
+            _ => 0b11,
+        }
+    }
+}
+
+/// Writes DR0-DR3 and DR7 so that they match `watchpoints`.
+pub fn program_watchpoints(watchpoints: &[Option<Watchpoint>; MAX_WATCHPOINTS]) {
+    let mut dr7 = 0u64;
+
+    for (slot, watchpoint) in watchpoints.iter().enumerate() {
+        let address = match watchpoint {
+            Some(watchpoint) => {
+                // Local enable, then R/W and LEN fields
+                dr7 |= 1 << (slot * 2);
+                dr7 |= watchpoint.kind.rw_bits() << (16 + slot * 4);
+                dr7 |= watchpoint.len_bits() << (18 + slot * 4);
+                watchpoint.address
+            }
+            None => 0,
+        };
+
+        unsafe {
+            match slot {
+                0 => asm!("mov dr0, {}", in(reg) address, options(nomem, nostack)),
+                1 => asm!("mov dr1, {}", in(reg) address, options(nomem, nostack)),
+                2 => asm!("mov dr2, {}", in(reg) address, options(nomem, nostack)),
+                _ => asm!("mov dr3, {}", in(reg) address, options(nomem, nostack)),
+            }
+        }
+    }
+
+    unsafe { asm!("mov dr7, {}", in(reg) dr7, options(nomem, nostack)) };
+}
+
+pub fn read_dr6() -> u64 {
+    let value: u64;
+    unsafe { asm!("mov {}, dr6", out(reg) value, options(nomem, nostack)) };
+    value
+}
+
+pub fn clear_dr6() {
+    unsafe { asm!("mov dr6, {}", in(reg) 0u64, options(nomem, nostack)) };
+}
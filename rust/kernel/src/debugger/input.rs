@@ -0,0 +1,57 @@
+use crate::display::force_writer;
+use crate::drivers::keyboard::{read_event, set_leds, Action, KeyType, BACKSPACE, KEYBOARD};
+
+/// Reads a line from the keyboard, echoing it on the console. With interrupts
+/// disabled `read_event` polls the controller.
+pub fn read_line(buffer: &mut [u8]) -> &str {
+    let mut len = 0;
+
+    loop {
+        let event = read_event();
+        if !event.is_pressed() {
+            continue;
+        }
+
+        let lock_key = matches!(
+            event.key,
+            KeyType::Action(Action::CapsLock | Action::NumLock | Action::ScrollLock)
+        );
+        if lock_key {
+            // The keyboard bottom half doesn't run while the debugger does
+            let leds = KEYBOARD.lock().leds();
+            let _ = set_leds(leds);
+            continue;
+        }
+
+        let chr = match event.char() {
+            Some(b'\n') => break,
+            Some(BACKSPACE) => {
+                if len > 0 {
+                    len -= 1;
+                    if let Some(mut writer) = force_writer() {
+                        writer.delete_last_char();
+                    }
+                }
+                continue;
+            }
+            // Accented letters are not ASCII, leave them out
+            Some(chr) if chr.is_ascii() && !chr.is_ascii_control() => chr,
+            _ => continue,
+        };
+
+        if len < buffer.len() {
+            buffer[len] = chr;
+            len += 1;
+            if let Some(mut writer) = force_writer() {
+                writer.write_byte(chr);
+            }
+        }
+    }
+
+    if let Some(mut writer) = force_writer() {
+        writer.new_line();
+    }
+
+    // Only ASCII is ever stored
+    core::str::from_utf8(&buffer[..len]).unwrap_or("")
+}
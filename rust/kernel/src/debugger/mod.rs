@@ -0,0 +1,387 @@
+// Interactive kernel debugger.
+//
+// Entered on `int3` (software breakpoints, or `breakpoint()` e.g. from the
+// F12 hotkey), on debug exceptions (single step and hardware watchpoints) and
+// on any fatal exception. It runs with interrupts disabled, reading the
+// keyboard events by polling and writing straight to the console.
+
+mod breakpoints;
+mod input;
+
+use core::arch::asm;
+use core::fmt::{self, Write};
+
+use crate::backtrace::write_backtrace;
+use crate::display::force_writer;
+use crate::drivers::keyboard::{keymap, set_keymap, KEYMAPS};
+use crate::interrupts::{exception_name, InterruptFrame};
+use crate::sync::IrqSpinLock;
+use crate::uaccess::{probe_read, probe_write};
+use breakpoints::{
+    clear_dr6, program_watchpoints, read_dr6, Breakpoint, WatchKind, Watchpoint,
+    DR6_HIT_MASK, DR6_SINGLE_STEP, MAX_BREAKPOINTS, MAX_WATCHPOINTS,
+};
+
+const VECTOR_DEBUG: u64 = 1;
+const VECTOR_BREAKPOINT: u64 = 3;
+
+// Longest dump of the `x` command
+const MAX_DUMP_LEN: u64 = 4096;
+
+// RFLAGS bits
+const RFLAGS_TF: u64 = 1 << 8;
+const RFLAGS_RF: u64 = 1 << 16;
+
+struct Debugger {
+    breakpoints: [Option<Breakpoint>; MAX_BREAKPOINTS],
+    watchpoints: [Option<Watchpoint>; MAX_WATCHPOINTS],
+    // Breakpoint lifted to execute the instruction under it, put back after one step
+    stepping_over: Option<usize>,
+    // The user asked for a single step
+    single_step: bool,
+}
+
+static DEBUGGER: IrqSpinLock<Debugger> = IrqSpinLock::new(Debugger {
+    breakpoints: [None; MAX_BREAKPOINTS],
+    watchpoints: [None; MAX_WATCHPOINTS],
+    stepping_over: None,
+    single_step: false,
+});
+
+/// Why the debugger was entered.
+enum Stop {
+    Breakpoint(usize),
+    Trap,
+    Step,
+    Watchpoint(u64),
+    Fault,
+}
+
+/// Drops into the debugger at the caller.
+#[inline(always)]
+pub fn breakpoint() {
+    unsafe { asm!("int3", options(nomem, nostack)) };
+}
+
+/// Entry point from `rust_exception_handler`. Returns true if execution can
+/// resume, false if the exception is fatal.
+pub fn handle_exception(frame: &mut InterruptFrame) -> bool {
+    // Taken by a session on this CPU: the exception comes from the debugger
+    // itself, e.g. a breakpoint in code it calls. Waiting would deadlock.
+    let Some(mut debugger) = DEBUGGER.try_lock() else {
+        out(format_args!(
+            "\nkdb: {} at {:#x} inside the debugger\n{}\n",
+            exception_name(frame.interrupt_number as u8),
+            frame.rip,
+            frame,
+        ));
+        return false;
+    };
+
+    match frame.interrupt_number {
+        VECTOR_BREAKPOINT => {
+            // RIP points after the int3
+            let address = frame.rip - 1;
+            let hit = debugger
+                .breakpoints
+                .iter()
+                .position(|bp| matches!(bp, Some(bp) if bp.inserted && bp.address == address));
+
+            let stop = match hit {
+                Some(index) => {
+                    frame.rip = address;
+                    Stop::Breakpoint(index)
+                }
+                None => Stop::Trap,
+            };
+            debugger.session(frame, stop);
+            true
+        }
+        VECTOR_DEBUG => {
+            let dr6 = read_dr6();
+            clear_dr6();
+
+            if dr6 & DR6_SINGLE_STEP != 0 {
+                if let Some(index) = debugger.stepping_over.take()
+                    && let Some(bp) = &mut debugger.breakpoints[index]
+                {
+                    let _ = bp.insert();
+                }
+
+                if debugger.single_step {
+                    debugger.session(frame, Stop::Step);
+                } else {
+                    frame.rflags &= !RFLAGS_TF;
+                }
+            }
+
+            if dr6 & DR6_HIT_MASK != 0 {
+                debugger.session(frame, Stop::Watchpoint(dr6 & DR6_HIT_MASK));
+            }
+            true
+        }
+        _ => {
+            debugger.session(frame, Stop::Fault);
+            false
+        }
+    }
+}
+
+impl Debugger {
+    fn session(&mut self, frame: &mut InterruptFrame, stop: Stop) {
+        match stop {
+            Stop::Breakpoint(index) => {
+                out(format_args!("\nkdb: breakpoint {} at {:#x}\n", index, frame.rip))
+            }
+            Stop::Trap => out(format_args!("\nkdb: break at {:#x}\n", frame.rip)),
+            Stop::Step => out(format_args!("kdb: step at {:#x}\n", frame.rip)),
+            Stop::Watchpoint(hits) => {
+                out(format_args!("\nkdb: watchpoint {:04b} hit at {:#x}\n", hits, frame.rip))
+            }
+            Stop::Fault => out(format_args!(
+                "\nkdb: {} ({}), error code {:#x}, cr2 {:#x}\n",
+                exception_name(frame.interrupt_number as u8),
+                frame.interrupt_number,
+                frame.error_code,
+                read_cr2(),
+            )),
+        }
+        out(format_args!("{}\n", frame));
+
+        let fatal = matches!(stop, Stop::Fault);
+        let mut buffer = [0u8; 64];
+
+        loop {
+            out(format_args!("kdb> "));
+            let line = input::read_line(&mut buffer);
+            let mut args = line.split_ascii_whitespace();
+            let Some(command) = args.next() else {
+                continue;
+            };
+
+            match command {
+                "c" | "continue" => {
+                    if fatal {
+                        out(format_args!("exception is fatal, cannot continue\n"));
+                        return;
+                    }
+                    self.resume(frame, false);
+                    return;
+                }
+                "s" | "step" if !fatal => {
+                    self.resume(frame, true);
+                    return;
+                }
+                "r" | "regs" => out(format_args!("{}\n", frame)),
+                "bt" => {
+                    if let Some(mut writer) = force_writer() {
+                        let _ = write_backtrace(&mut *writer, frame.rip, frame.rbp);
+                    }
+                }
+                "set" => match (args.next(), args.next().and_then(parse_number)) {
+                    (Some(name), Some(value)) => match register(frame, name) {
+                        Some(reg) => *reg = value,
+                        None => out(format_args!("unknown register {}\n", name)),
+                    },
+                    _ => out(format_args!("usage: set REG VALUE\n")),
+                },
+                "x" => match args.next().and_then(parse_number) {
+                    Some(address) => {
+                        let len = args.next().and_then(parse_number).unwrap_or(64);
+                        dump_memory(address, len);
+                    }
+                    None => out(format_args!("usage: x ADDR [LEN]\n")),
+                },
+                "w" => match args.next().and_then(parse_number) {
+                    Some(address) => {
+                        for (offset, byte) in args.filter_map(parse_number).enumerate() {
+                            let target = address.wrapping_add(offset as u64) as *mut u8;
+                            if unsafe { probe_write(target, byte as u8) }.is_err() {
+                                out(format_args!("cannot write {:#x}\n", target as u64));
+                                break;
+                            }
+                        }
+                    }
+                    None => out(format_args!("usage: w ADDR BYTE...\n")),
+                },
+                "b" => match args.next().and_then(parse_number) {
+                    Some(address) => self.add_breakpoint(address),
+                    None => out(format_args!("usage: b ADDR\n")),
+                },
+                "bc" => match args.next().and_then(parse_number) {
+                    Some(index) => self.clear_breakpoint(index as usize),
+                    None => out(format_args!("usage: bc N\n")),
+                },
+                "wp" => {
+                    let slot = args.next().and_then(parse_number);
+                    let address = args.next().and_then(parse_number);
+                    let len = args.next().and_then(parse_number);
+                    let kind = match args.next() {
+                        Some("x") => Some(WatchKind::Execute),
+                        Some("w") => Some(WatchKind::Write),
+                        Some("r") | Some("rw") => Some(WatchKind::ReadWrite),
+                        _ => None,
+                    };
+                    match (slot, address, len, kind) {
+                        (Some(slot), Some(address), Some(len), Some(kind))
+                            if (slot as usize) < MAX_WATCHPOINTS && matches!(len, 1 | 2 | 4 | 8) =>
+                        {
+                            let watchpoint = Watchpoint { address, len: len as u8, kind };
+                            self.watchpoints[slot as usize] = Some(watchpoint);
+                            program_watchpoints(&self.watchpoints);
+                        }
+                        _ => out(format_args!("usage: wp SLOT(0-3) ADDR LEN(1,2,4,8) r|w|x\n")),
+                    }
+                }
+                "wc" => match args.next().and_then(parse_number) {
+                    Some(slot) if (slot as usize) < MAX_WATCHPOINTS => {
+                        self.watchpoints[slot as usize] = None;
+                        program_watchpoints(&self.watchpoints);
+                    }
+                    _ => out(format_args!("usage: wc SLOT\n")),
+                },
+                "bl" => self.list(),
+                "keymap" => match args.next() {
+                    Some(name) => {
+                        if set_keymap(name).is_err() {
+                            out(format_args!("unknown layout {}\n", name));
+                        }
+                    }
+                    None => {
+                        out(format_args!("layout {}, available:", keymap().name));
+                        KEYMAPS.iter().for_each(|map| out(format_args!(" {}", map.name)));
+                        out(format_args!("\n"));
+                    }
+                },
+                "h" | "help" => out(format_args!(
+                    "c continue   s step   r regs   bt backtrace   set REG VAL\n\
+                     x ADDR [LEN] dump     w ADDR BYTE... write\n\
+                     b ADDR break   bc N clear   bl list\n\
+                     wp SLOT ADDR LEN r|w|x watch   wc SLOT clear\n\
+                     keymap [NAME] keyboard layout\n"
+                )),
+                _ => out(format_args!("unknown command, try help\n")),
+            }
+        }
+    }
+
+    fn resume(&mut self, frame: &mut InterruptFrame, single_step: bool) {
+        self.single_step = single_step;
+
+        // Execute the original instruction under a breakpoint before putting it back
+        let lifted = self
+            .breakpoints
+            .iter()
+            .position(|bp| matches!(bp, Some(bp) if bp.address == frame.rip));
+        if let Some(index) = lifted
+            && let Some(bp) = &mut self.breakpoints[index]
+        {
+            let _ = bp.remove();
+            self.stepping_over = Some(index);
+        }
+
+        if single_step || self.stepping_over.is_some() {
+            frame.rflags |= RFLAGS_TF;
+        } else {
+            frame.rflags &= !RFLAGS_TF;
+        }
+        // Do not trigger an execution watchpoint again on the same instruction
+        frame.rflags |= RFLAGS_RF;
+    }
+
+    fn add_breakpoint(&mut self, address: u64) {
+        let Some(slot) = self.breakpoints.iter().position(|bp| bp.is_none()) else {
+            out(format_args!("no free breakpoint\n"));
+            return;
+        };
+
+        match Breakpoint::new(address).and_then(|mut bp| bp.insert().map(|_| bp)) {
+            Ok(bp) => {
+                self.breakpoints[slot] = Some(bp);
+                out(format_args!("breakpoint {} at {:#x}\n", slot, address));
+            }
+            Err(_) => out(format_args!("cannot access {:#x}\n", address)),
+        }
+    }
+
+    fn clear_breakpoint(&mut self, index: usize) {
+        match self.breakpoints.get_mut(index).and_then(|bp| bp.take()) {
+            Some(mut bp) => {
+                let _ = bp.remove();
+                if self.stepping_over == Some(index) {
+                    self.stepping_over = None;
+                }
+            }
+            None => out(format_args!("no breakpoint {}\n", index)),
+        }
+    }
+
+    fn list(&self) {
+        for (index, bp) in self.breakpoints.iter().enumerate() {
+            if let Some(bp) = bp {
+                out(format_args!("b{} {:#x}\n", index, bp.address));
+            }
+        }
+        for (slot, wp) in self.watchpoints.iter().enumerate() {
+            if let Some(wp) = wp {
+                out(format_args!("w{} {:#x} len {} {:?}\n", slot, wp.address, wp.len, wp.kind));
+            }
+        }
+    }
+}
+
+fn register<'a>(frame: &'a mut InterruptFrame, name: &str) -> Option<&'a mut u64> {
+    Some(match name {
+        "rax" => &mut frame.rax,
+        "rbx" => &mut frame.rbx,
+        "rcx" => &mut frame.rcx,
+        "rdx" => &mut frame.rdx,
+        "rsi" => &mut frame.rsi,
+        "rdi" => &mut frame.rdi,
+        "rbp" => &mut frame.rbp,
+        "rsp" => &mut frame.rsp,
+        "r8" => &mut frame.r8,
+        "r9" => &mut frame.r9,
+        "r10" => &mut frame.r10,
+        "r11" => &mut frame.r11,
+        "r12" => &mut frame.r12,
+        "r13" => &mut frame.r13,
+        "r14" => &mut frame.r14,
+        "r15" => &mut frame.r15,
+        "rip" => &mut frame.rip,
+        "rflags" => &mut frame.rflags,
+        _ => return None,
+    })
+}
+
+fn dump_memory(address: u64, len: u64) {
+    let len = len.min(MAX_DUMP_LEN);
+    for line in (0..len).step_by(16) {
+        out(format_args!("{:016x}:", address.wrapping_add(line)));
+        for offset in line..line.saturating_add(16).min(len) {
+            match probe_read(address.wrapping_add(offset) as *const u8) {
+                Ok(byte) => out(format_args!(" {:02x}", byte)),
+                Err(_) => out(format_args!(" ??")),
+            }
+        }
+        out(format_args!("\n"));
+    }
+}
+
+/// Parses a hexadecimal number, with or without the 0x prefix.
+fn parse_number(text: &str) -> Option<u64> {
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    u64::from_str_radix(digits, 16).ok()
+}
+
+fn read_cr2() -> u64 {
+    let value: u64;
+    unsafe { asm!("mov {}, cr2", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+fn out(args: fmt::Arguments) {
+    if let Some(mut writer) = force_writer() {
+        let _ = writer.write_fmt(args);
+    }
+}
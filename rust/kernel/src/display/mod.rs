@@ -1,23 +1,53 @@
 pub(crate) mod vga;
 
-use core::mem::MaybeUninit;
+use core::fmt;
+
+use crate::sync::{IrqSpinLock, IrqSpinLockGuard, OnceCell};
 use vga::{Writer, Color};
 
-static mut WRITER: MaybeUninit<Writer> = MaybeUninit::uninit();
+static WRITER: OnceCell<IrqSpinLock<Writer>> = OnceCell::new();
 
-// TODO: remove the allow and replace with MUTEX
-#[allow(static_mut_refs)]
-pub unsafe fn init_writer() {
-    WRITER.write(Writer::new(
+pub fn init_writer() {
+    WRITER.call_once(|| IrqSpinLock::new(Writer::new(
         Color::LightGray,
         Color::Black,
         0xB8000 as *mut u16,
-    ));
+    )));
 }
 
+/// Locks the console. Interrupts stay disabled until the guard is dropped, so
+/// keep it short-lived.
 #[inline]
-// TODO: remove the allow and replace with MUTEX
-#[allow(static_mut_refs)]
-pub unsafe fn writer() -> &'static mut Writer {
-    WRITER.assume_init_mut()
+pub fn writer() -> IrqSpinLockGuard<'static, Writer> {
+    WRITER.get().expect("VGA writer not initialized").lock()
+}
+
+/// Console access for the panic handler and the debugger. A lock held by the
+/// interrupted code is broken, since its holder cannot run until we are done.
+pub fn force_writer() -> Option<IrqSpinLockGuard<'static, Writer>> {
+    let lock = WRITER.get()?;
+    unsafe { lock.force_unlock() };
+    Some(lock.lock())
+}
+
+/// Like the `print!` macro in the standard library, but prints to the VGA text buffer.
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::display::_print(format_args!($($arg)*)));
+}
+
+/// Like the `println!` macro in the standard library, but prints to the VGA text buffer.
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    use core::fmt::Write;
+    // Printing before the console is up is silently dropped
+    if let Some(writer) = WRITER.get() {
+        let _ = writer.lock().write_fmt(args);
+    }
 }
@@ -1,7 +1,7 @@
 use core::fmt;
 
-const VGA_WIDTH: usize = 80;
-const VGA_HEIGHT: usize = 25;
+pub const VGA_WIDTH: usize = 80;
+pub const VGA_HEIGHT: usize = 25;
 
 
 #[allow(dead_code)]
@@ -34,6 +34,11 @@ impl ColorCode {
     const fn new(foreground: Color, background: Color) -> ColorCode {
         ColorCode((background as u8) << 4 | (foreground as u8))
     }
+
+    // Swaps foreground and background
+    const fn inverted(self) -> ColorCode {
+        ColorCode(self.0.rotate_left(4))
+    }
 }
 
 #[repr(C)]
@@ -61,7 +66,9 @@ impl Buffer {
 pub struct Writer {
     column_position: usize,
     color_code: ColorCode,
-    buffer: &'static mut Buffer
+    buffer: &'static mut Buffer,
+    // Cell shown in inverted colors as the mouse cursor, (row, column)
+    mouse_cursor: Option<(usize, usize)>,
 }
 
 impl Writer {
@@ -71,6 +78,7 @@ impl Writer {
             column_position: 0,
             color_code: ColorCode::new(foreground, background),
             buffer: unsafe { &mut *(buffer_ptr as *mut Buffer) },
+            mouse_cursor: None,
         }
     }
     
@@ -111,7 +119,38 @@ impl Writer {
             color_code: self.color_code,
             ascii_code: byte
         };
-        self.buffer.write(row, col, bytes);
+        self.put(row, col, bytes);
+    }
+
+    // Writes a cell, keeping the mouse cursor visible over it
+    fn put(&mut self, row: usize, col: usize, mut char: ScreenChar) {
+        if self.mouse_cursor == Some((row, col)) {
+            char.color_code = char.color_code.inverted();
+        }
+        self.buffer.write(row, col, char);
+    }
+
+    fn invert(&mut self, (row, col): (usize, usize)) {
+        let mut char = self.buffer.read(row, col);
+        char.color_code = char.color_code.inverted();
+        self.buffer.write(row, col, char);
+    }
+
+    /// Moves the mouse cursor, a block in inverted colors, to `(row, column)`
+    /// or hides it.
+    pub fn set_mouse_cursor(&mut self, position: Option<(usize, usize)>) {
+        let position = position.filter(|&(row, col)| row < VGA_HEIGHT && col < VGA_WIDTH);
+        if position == self.mouse_cursor {
+            return;
+        }
+
+        if let Some(old) = self.mouse_cursor {
+            self.invert(old);
+        }
+        if let Some(new) = position {
+            self.invert(new);
+        }
+        self.mouse_cursor = position;
     }
 
     pub fn delete_last_char(&mut self) {
@@ -120,17 +159,20 @@ impl Writer {
             ascii_code: b' '
         };
 
-        if self.column_position == 0 {
-            todo!()
-        } else {
+        // Lines that scrolled up are not edited again
+        if self.column_position > 0 {
             self.column_position -= 1;
-            self.buffer.write(VGA_HEIGHT - 1, self.column_position, value);
+            self.put(VGA_HEIGHT - 1, self.column_position, value);
         }
     }
 
     pub fn new_line(&mut self) {
         self.column_position = 0;
-        
+
+        // The cursor stays where it is while the text scrolls
+        let cursor = self.mouse_cursor;
+        self.set_mouse_cursor(None);
+
         for i in 0..(VGA_HEIGHT - 1) {
             for j in 0..VGA_WIDTH {
                 let value = self.buffer.read(i + 1, j);
@@ -145,6 +187,7 @@ impl Writer {
         for i in 0..VGA_WIDTH {
             self.buffer.write(VGA_HEIGHT - 1, i, value);
         }
+        self.set_mouse_cursor(cursor);
     }
 }
 
@@ -0,0 +1,163 @@
+// Key event queue.
+//
+// The IRQ handler decodes scancodes and pushes the resulting events here.
+// Readers take them with `read_event`, which sleeps until a key arrives, or
+// `try_read_event`. With interrupts disabled the blocking reads poll the
+// controller instead. The console echo is a reader too: turn it off with
+// `set_echo(false)` before reading.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use super::keycode::{KeyCode, Modifiers};
+use super::{poll_scancode, Action, KeyState, KeyType};
+use crate::cpu::interrupts_enabled;
+use crate::display::writer;
+use crate::ring::RingBuffer;
+use crate::time::idle;
+use crate::watchdog;
+use crate::workqueue;
+
+/// Character of the Backspace key, see `KeyEvent::char`.
+pub const BACKSPACE: u8 = 0x08;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct KeyEvent {
+    pub key: KeyType,
+    pub state: KeyState,
+    pub code: KeyCode,
+    /// Modifiers held, this key included
+    pub modifiers: Modifiers,
+}
+
+impl KeyEvent {
+    pub fn is_pressed(&self) -> bool {
+        self.state == KeyState::Pressed
+    }
+
+    /// Character typed by a key press: printable keys, `\n`, `\t`, space and
+    /// backspace (0x08).
+    pub fn char(&self) -> Option<u8> {
+        if !self.is_pressed() {
+            return None;
+        }
+
+        match self.key {
+            KeyType::Action(Action::Enter) => Some(b'\n'),
+            KeyType::Action(Action::Space) => Some(b' '),
+            KeyType::Action(Action::Tab) => Some(b'\t'),
+            KeyType::Action(Action::Backspace) => Some(BACKSPACE),
+            key => key.print(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EventStats {
+    pub queued: u64,
+    /// Events lost because the queue was full
+    pub dropped: u64,
+}
+
+static EVENTS: RingBuffer<KeyEvent, 128> = RingBuffer::new();
+static QUEUED: AtomicU64 = AtomicU64::new(0);
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+// Set when an event is dropped, until `take_overflow`
+static OVERFLOW: AtomicBool = AtomicBool::new(false);
+
+pub(super) fn push(event: KeyEvent) {
+    if EVENTS.push(event).is_ok() {
+        QUEUED.fetch_add(1, Ordering::Relaxed);
+    } else {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+        OVERFLOW.store(true, Ordering::Relaxed);
+    }
+}
+
+pub fn try_read_event() -> Option<KeyEvent> {
+    EVENTS.pop()
+}
+
+/// Waits for the next key event. Bottom halves keep running meanwhile.
+pub fn read_event() -> KeyEvent {
+    loop {
+        if let Some(event) = EVENTS.pop() {
+            return event;
+        }
+
+        if !interrupts_enabled() {
+            // Nothing will fill the queue, feed it from the controller
+            match poll_scancode() {
+                Some(scancode) => super::handle_scancode(scancode),
+                None => core::hint::spin_loop(),
+            }
+            continue;
+        }
+
+        // Waiting for the user is not a lockup
+        watchdog::touch();
+        workqueue::run_pending();
+
+        // Same as the idle loop: check for a key with interrupts off so a
+        // wakeup can't be lost before the halt
+        unsafe {
+            core::arch::asm!("cli", options(nostack));
+        }
+        if EVENTS.is_empty() && !workqueue::has_pending() {
+            idle::idle();
+        } else {
+            unsafe {
+                core::arch::asm!("sti", options(nostack));
+            }
+        }
+    }
+}
+
+/// Waits for a key press that types a character, see `KeyEvent::char`.
+pub fn read_char() -> u8 {
+    loop {
+        if let Some(chr) = read_event().char() {
+            return chr;
+        }
+    }
+}
+
+/// Reads a line into `buffer`, echoing it on the console. Returns the line
+/// without the newline, in code page 437. Characters past the end of the
+/// buffer are dropped.
+pub fn read_line(buffer: &mut [u8]) -> &[u8] {
+    let mut len = 0;
+
+    loop {
+        match read_char() {
+            b'\n' => break,
+            BACKSPACE => {
+                if len > 0 {
+                    len -= 1;
+                    writer().delete_last_char();
+                }
+            }
+            chr => {
+                if len < buffer.len() {
+                    buffer[len] = chr;
+                    len += 1;
+                    writer().write_byte(chr);
+                }
+            }
+        }
+    }
+
+    writer().new_line();
+    &buffer[..len]
+}
+
+pub fn stats() -> EventStats {
+    EventStats {
+        queued: QUEUED.load(Ordering::Relaxed),
+        dropped: DROPPED.load(Ordering::Relaxed),
+    }
+}
+
+/// True if events were dropped since the last call.
+pub fn take_overflow() -> bool {
+    OVERFLOW.swap(false, Ordering::Relaxed)
+}
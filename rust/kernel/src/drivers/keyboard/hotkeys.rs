@@ -0,0 +1,191 @@
+// Global hotkeys.
+//
+// A hotkey is a key pressed with a set of modifiers, matched on the keycode
+// so it works the same whatever the layout. Matching key presses and their
+// releases don't reach the event queue, the action runs from the keyboard
+// bottom half.
+
+use core::arch::asm;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use super::events::{self, KeyEvent};
+use super::keycode::{KeyCode, Modifiers};
+use crate::debugger;
+use crate::display::writer;
+use crate::drivers::ps2;
+use crate::interrupts;
+use crate::ring::RingBuffer;
+use crate::sync::IrqSpinLock;
+use crate::time;
+use crate::workqueue;
+
+const MAX_HOTKEYS: usize = 16;
+
+pub type HotkeyFn = fn();
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum HotkeyError {
+    TooManyHotkeys,
+    /// The combination already has an action
+    InUse,
+}
+
+#[derive(Clone, Copy)]
+struct Hotkey {
+    name: &'static str,
+    modifiers: Modifiers,
+    code: KeyCode,
+    action: HotkeyFn,
+}
+
+static HOTKEYS: IrqSpinLock<[Option<Hotkey>; MAX_HOTKEYS]> =
+    IrqSpinLock::new([None; MAX_HOTKEYS]);
+
+// Actions of the hotkeys pressed, run by the bottom half
+static PENDING: RingBuffer<HotkeyFn, 8> = RingBuffer::new();
+
+// Keycodes whose press was taken by a hotkey, one bit each: their release is
+// taken as well, whatever the modifiers are by then
+static SWALLOWED: [AtomicU64; 4] = [const { AtomicU64::new(0) }; 4];
+
+// Set by Ctrl+C
+static INTERRUPT: AtomicBool = AtomicBool::new(false);
+
+/// Runs `action` when `code` is pressed with `modifiers`, on either side.
+pub fn register_hotkey(
+    name: &'static str,
+    modifiers: Modifiers,
+    code: KeyCode,
+    action: HotkeyFn,
+) -> Result<(), HotkeyError> {
+    let mut hotkeys = HOTKEYS.lock();
+    let taken = hotkeys
+        .iter()
+        .flatten()
+        .any(|hotkey| hotkey.code == code && hotkey.modifiers.matches(modifiers));
+    if taken {
+        return Err(HotkeyError::InUse);
+    }
+
+    let slot = hotkeys
+        .iter_mut()
+        .find(|slot| slot.is_none())
+        .ok_or(HotkeyError::TooManyHotkeys)?;
+    *slot = Some(Hotkey { name, modifiers, code, action });
+    Ok(())
+}
+
+/// Removes a hotkey, returning false if there was none.
+pub fn unregister_hotkey(modifiers: Modifiers, code: KeyCode) -> bool {
+    let mut hotkeys = HOTKEYS.lock();
+    let slot = hotkeys.iter_mut().find(|slot| {
+        matches!(slot, Some(hotkey) if hotkey.code == code && hotkey.modifiers.matches(modifiers))
+    });
+    match slot {
+        Some(slot) => {
+            *slot = None;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Queues the action of the hotkey `event` presses, if any. True if the
+/// event was taken, which is also the case of the release of such a key.
+pub(super) fn trigger(event: &KeyEvent) -> bool {
+    let swallowed = &SWALLOWED[event.code.0 as usize / 64];
+    let bit = 1 << (event.code.0 % 64);
+    if !event.is_pressed() {
+        return swallowed.fetch_and(!bit, Ordering::Relaxed) & bit != 0;
+    }
+
+    let hotkeys = HOTKEYS.lock();
+    let hotkey = hotkeys
+        .iter()
+        .flatten()
+        .find(|hotkey| hotkey.code == event.code && event.modifiers.matches(hotkey.modifiers));
+    match hotkey {
+        // A full queue means the bottom half is far behind, the key is lost
+        Some(hotkey) => {
+            let _ = PENDING.push(hotkey.action);
+            swallowed.fetch_or(bit, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
+
+pub(super) fn run_pending() {
+    while let Some(action) = PENDING.pop() {
+        action();
+    }
+}
+
+/// True if Ctrl+C was pressed since the last call. The hotkey keeps Ctrl+C
+/// out of the event queue, readers only see it through this.
+pub fn take_interrupt() -> bool {
+    INTERRUPT.swap(false, Ordering::Relaxed)
+}
+
+/// Installs the built-in hotkeys.
+pub(super) fn init() {
+    let builtins: [(&'static str, Modifiers, KeyCode, HotkeyFn); 6] = [
+        ("reboot", Modifiers::CTRL | Modifiers::ALT, KeyCode::DELETE, reboot),
+        ("reboot", Modifiers::CTRL | Modifiers::ALT, KeyCode::KEYPAD_DELETE, reboot),
+        ("debug dump", Modifiers::ALT, KeyCode::SYSRQ, debug_dump),
+        // Emulators may not turn Alt+Print Screen into SysRq
+        ("debug dump", Modifiers::ALT, KeyCode::PRINT_SCREEN, debug_dump),
+        ("interrupt", Modifiers::CTRL, KeyCode::C, interrupt),
+        ("debugger", Modifiers::NONE, KeyCode::F12, debugger::breakpoint),
+    ];
+    for (name, modifiers, code, action) in builtins {
+        // The table is empty at this point
+        let _ = register_hotkey(name, modifiers, code, action);
+    }
+}
+
+fn reboot() {
+    writer().write("Rebooting...\n");
+    ps2::pulse_reset();
+
+    // Still there: triple fault, with an empty IDT
+    let null_idt = [0u16; 5];
+    unsafe {
+        asm!("cli", "lidt [{}]", "int3", in(reg) null_idt.as_ptr(), options(noreturn));
+    }
+}
+
+fn debug_dump() {
+    let _ = write_dump(&mut *writer());
+}
+
+fn write_dump(w: &mut impl Write) -> fmt::Result {
+    writeln!(w, "--- SysRq: uptime {} ms ---", time::uptime())?;
+    interrupts::stats::dump(w)?;
+    workqueue::dump(w)?;
+    time::timer::dump(w)?;
+    time::idle::dump(w)?;
+    dump(w)?;
+    let stats = events::stats();
+    writeln!(w, "keyboard: {} events, {} dropped", stats.queued, stats.dropped)
+}
+
+fn interrupt() {
+    INTERRUPT.store(true, Ordering::Relaxed);
+    writer().write("^C\n");
+}
+
+// Prints the registered hotkeys
+fn dump(w: &mut impl Write) -> fmt::Result {
+    let hotkeys = *HOTKEYS.lock();
+    writeln!(w, "{:<12} {:>9} {:>8}", "hotkey", "modifiers", "keycode")?;
+    for hotkey in hotkeys.iter().flatten() {
+        writeln!(w, "{:<12} {:>#9x} {:>#8x}",
+            hotkey.name,
+            hotkey.modifiers.bits(),
+            hotkey.code.0,
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,110 @@
+// Layout independent key identification.
+
+use core::ops::BitOr;
+
+/// Physical key, stable across layouts: the set 1 make code, with bit 7 set
+/// for keys behind the 0xE0 prefix.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct KeyCode(pub u8);
+
+impl KeyCode {
+    pub const ESCAPE: KeyCode = KeyCode(0x01);
+    pub const C: KeyCode = KeyCode(0x2E);
+    pub const KEYPAD_DELETE: KeyCode = KeyCode(0x53);
+    /// Print Screen with Alt held
+    pub const SYSRQ: KeyCode = KeyCode(0x54);
+    pub const F12: KeyCode = KeyCode(0x58);
+    pub const PRINT_SCREEN: KeyCode = KeyCode(0x80 | 0x37);
+    pub const DELETE: KeyCode = KeyCode(0x80 | 0x53);
+    /// Pause has no make code of its own
+    pub const PAUSE: KeyCode = KeyCode(0xFF);
+
+    pub const fn extended(scancode: u8) -> KeyCode {
+        KeyCode(0x80 | scancode)
+    }
+
+    pub fn is_extended(self) -> bool {
+        self != Self::PAUSE && self.0 & 0x80 != 0
+    }
+}
+
+/// Modifier keys held down, telling left and right apart.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers(0);
+    pub const LEFT_SHIFT: Modifiers = Modifiers(1 << 0);
+    pub const RIGHT_SHIFT: Modifiers = Modifiers(1 << 1);
+    pub const LEFT_CTRL: Modifiers = Modifiers(1 << 2);
+    pub const RIGHT_CTRL: Modifiers = Modifiers(1 << 3);
+    pub const LEFT_ALT: Modifiers = Modifiers(1 << 4);
+    /// Right Alt, AltGr on most layouts
+    pub const RIGHT_ALT: Modifiers = Modifiers(1 << 5);
+    pub const LEFT_SUPER: Modifiers = Modifiers(1 << 6);
+    pub const RIGHT_SUPER: Modifiers = Modifiers(1 << 7);
+
+    // Either side
+    pub const SHIFT: Modifiers = Modifiers(Self::LEFT_SHIFT.0 | Self::RIGHT_SHIFT.0);
+    pub const CTRL: Modifiers = Modifiers(Self::LEFT_CTRL.0 | Self::RIGHT_CTRL.0);
+    pub const ALT: Modifiers = Modifiers(Self::LEFT_ALT.0 | Self::RIGHT_ALT.0);
+    pub const ALTGR: Modifiers = Self::RIGHT_ALT;
+    pub const SUPER: Modifiers = Modifiers(Self::LEFT_SUPER.0 | Self::RIGHT_SUPER.0);
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub const fn union(self, other: Modifiers) -> Modifiers {
+        Modifiers(self.0 | other.0)
+    }
+
+    /// True if any of the keys of `other` is held.
+    pub const fn intersects(self, other: Modifiers) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    pub fn shift(self) -> bool {
+        self.intersects(Self::SHIFT)
+    }
+
+    pub fn ctrl(self) -> bool {
+        self.intersects(Self::CTRL)
+    }
+
+    pub fn alt(self) -> bool {
+        self.intersects(Self::ALT)
+    }
+
+    pub fn altgr(self) -> bool {
+        self.intersects(Self::ALTGR)
+    }
+
+    pub fn super_key(self) -> bool {
+        self.intersects(Self::SUPER)
+    }
+
+    pub(super) fn set(&mut self, modifier: Modifiers, held: bool) {
+        if held {
+            self.0 |= modifier.0;
+        } else {
+            self.0 &= !modifier.0;
+        }
+    }
+
+    /// True if the same kinds of modifiers are held as in `required`,
+    /// whatever the side.
+    pub fn matches(self, required: Modifiers) -> bool {
+        [Self::SHIFT, Self::CTRL, Self::ALT, Self::SUPER]
+            .iter()
+            .all(|&kind| self.intersects(kind) == required.intersects(kind))
+    }
+}
+
+impl BitOr for Modifiers {
+    type Output = Modifiers;
+
+    fn bitor(self, other: Modifiers) -> Modifiers {
+        self.union(other)
+    }
+}
@@ -0,0 +1,167 @@
+// Keyboard layouts.
+//
+// A keymap gives the character of every main block key for each of the four
+// levels: normal, Shift, AltGr and Shift+AltGr. Characters are code page 437
+// bytes, the encoding of the VGA text mode, so accented letters print as
+// such. A 0 entry means the key prints nothing on that level.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// Scancodes 0x00-0x56, 0x56 being the extra key of ISO keyboards
+const KEYMAP_SIZE: usize = 0x57;
+const ISO_KEY: usize = 0x56;
+
+// First scancode of each row
+const DIGITS_ROW: usize = 0x02;
+const TOP_ROW: usize = 0x10;
+const HOME_ROW: usize = 0x1E;
+const BOTTOM_ROW: usize = 0x2B;
+
+const LEVEL_SHIFT: usize = 1;
+const LEVEL_ALTGR: usize = 2;
+
+pub type Level = [u8; KEYMAP_SIZE];
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum KeymapError {
+    UnknownLayout,
+}
+
+pub struct Keymap {
+    pub name: &'static str,
+    levels: [Level; 4],
+}
+
+impl Keymap {
+    /// Character of `scancode`, 0 if it has none.
+    pub fn translate(&self, scancode: u8, shift: bool, altgr: bool) -> u8 {
+        let mut level = 0;
+        if shift {
+            level |= LEVEL_SHIFT;
+        }
+        if altgr {
+            level |= LEVEL_ALTGR;
+        }
+        self.levels[level].get(scancode as usize).copied().unwrap_or(0)
+    }
+
+    /// True if Shift turns the key into the upper case of the same letter,
+    /// which is when Caps Lock applies to it.
+    pub fn is_letter(&self, scancode: u8) -> bool {
+        let lower = self.translate(scancode, false, false);
+        let upper = self.translate(scancode, true, false);
+        (lower.is_ascii_lowercase() && upper == lower.to_ascii_uppercase())
+            || CP437_CASE_PAIRS.contains(&(lower, upper))
+    }
+}
+
+// Builds a level from its rows, in scancode order: `digits` starts at the 1
+// key, `home` ends with the key left of 1 and `bottom` starts with the key
+// left of Z on US keyboards (next to Enter on ISO ones).
+const fn level(digits: &[u8; 12], top: &[u8; 12], home: &[u8; 12], bottom: &[u8; 11], iso: u8)
+    -> Level
+{
+    let mut level = [0; KEYMAP_SIZE];
+    let mut i = 0;
+    while i < 12 {
+        level[DIGITS_ROW + i] = digits[i];
+        level[TOP_ROW + i] = top[i];
+        level[HOME_ROW + i] = home[i];
+        if i < 11 {
+            level[BOTTOM_ROW + i] = bottom[i];
+        }
+        i += 1;
+    }
+    level[ISO_KEY] = iso;
+    level
+}
+
+// Lower and upper case letters of code page 437 used by the layouts
+const CP437_CASE_PAIRS: [(u8, u8); 7] = [
+    (0x81, 0x9A),               // ü
+    (0x82, 0x90),               // é
+    (0x84, 0x8E),               // ä
+    (0x86, 0x8F),               // å
+    (0x87, 0x80),               // ç
+    (0x94, 0x99),               // ö
+    (0xA4, 0xA5),               // ñ
+];
+
+const EMPTY: Level = [0; KEYMAP_SIZE];
+
+pub static US: Keymap = Keymap {
+    name: "us",
+    levels: [
+        level(b"1234567890-=", b"qwertyuiop[]", b"asdfghjkl;'`", b"\\zxcvbnm,./", b'\\'),
+        level(b"!@#$%^&*()_+", b"QWERTYUIOP{}", b"ASDFGHJKL:\"~", b"|ZXCVBNM<>?", b'|'),
+        EMPTY,
+        EMPTY,
+    ],
+};
+
+pub static UK: Keymap = Keymap {
+    name: "uk",
+    levels: [
+        level(b"1234567890-=", b"qwertyuiop[]", b"asdfghjkl;'`", b"#zxcvbnm,./", b'\\'),
+        level(b"!\"\x9C$%^&*()_+", b"QWERTYUIOP{}", b"ASDFGHJKL:@\xAA", b"~ZXCVBNM<>?", b'|'),
+        EMPTY,
+        EMPTY,
+    ],
+};
+
+pub static IT: Keymap = Keymap {
+    name: "it",
+    levels: [
+        level(b"1234567890'\x8D", b"qwertyuiop\x8A+", b"asdfghjkl\x95\x85\\", b"\x97zxcvbnm,.-",
+            b'<'),
+        level(b"!\"\x9C$%&/()=?^", b"QWERTYUIOP\x82*", b"ASDFGHJKL\x87\xF8|", b"\x15ZXCVBNM;:_",
+            b'>'),
+        level(&[0; 12], b"\0\0\0\0\0\0\0\0\0\0[]", b"\0\0\0\0\0\0\0\0\0@#\0", &[0; 11], 0),
+        level(&[0; 12], b"\0\0\0\0\0\0\0\0\0\0{}", &[0; 12], &[0; 11], 0),
+    ],
+};
+
+pub static DE: Keymap = Keymap {
+    name: "de",
+    levels: [
+        level(b"1234567890\xE1\0", b"qwertzuiop\x81+", b"asdfghjkl\x94\x84^", b"#yxcvbnm,.-",
+            b'<'),
+        level(b"!\"\x15$%&/()=?`", b"QWERTZUIOP\x9A*", b"ASDFGHJKL\x99\x8E\xF8", b"'YXCVBNM;:_",
+            b'>'),
+        level(b"\0\xFD\0\0\0\0{[]}\\\0", b"@\0\0\0\0\0\0\0\0\0\0~", &[0; 12],
+            b"\0\0\0\0\0\0\0\xE6\0\0\0", b'|'),
+        EMPTY,
+    ],
+};
+
+pub static FR: Keymap = Keymap {
+    name: "fr",
+    levels: [
+        level(b"&\x82\"'(-\x8A_\x87\x85)=", b"azertyuiop^$", b"qsdfghjklm\x97\xFD",
+            b"*wxcvbn,;:!", b'<'),
+        level(b"1234567890\xF8+", b"AZERTYUIOP\0\x9C", b"QSDFGHJKLM%\0", b"\xE6WXCVBN?./\x15",
+            b'>'),
+        level(b"\0~#{[|`\\^@]}", &[0; 12], &[0; 12], &[0; 11], 0),
+        EMPTY,
+    ],
+};
+
+pub static KEYMAPS: [&Keymap; 5] = [&US, &UK, &IT, &DE, &FR];
+
+// Index in `KEYMAPS` of the layout in use
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+
+pub fn keymap() -> &'static Keymap {
+    KEYMAPS[CURRENT.load(Ordering::Relaxed)]
+}
+
+/// Switches to the layout called `name`, e.g. "it". Takes effect on the next
+/// key.
+pub fn set_keymap(name: &str) -> Result<(), KeymapError> {
+    let index = KEYMAPS
+        .iter()
+        .position(|keymap| keymap.name.eq_ignore_ascii_case(name))
+        .ok_or(KeymapError::UnknownLayout)?;
+    CURRENT.store(index, Ordering::Relaxed);
+    Ok(())
+}
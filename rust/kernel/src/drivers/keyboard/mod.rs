@@ -0,0 +1,179 @@
+mod events;
+mod hotkeys;
+mod keycode;
+mod keymap;
+mod scancode;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::ps2::{self, Port, Ps2Error};
+use crate::display::writer;
+use crate::interrupts::{register_irq_handler, InterruptFrame, IrqReturn};
+use crate::sync::IrqSpinLock;
+use crate::workqueue::{self, Work};
+
+pub use events::{
+    read_char, read_event, read_line, stats, take_overflow, try_read_event, EventStats, KeyEvent,
+    BACKSPACE,
+};
+pub use hotkeys::{register_hotkey, take_interrupt, unregister_hotkey, HotkeyError, HotkeyFn};
+pub use keycode::{KeyCode, Modifiers};
+pub use keymap::{keymap, set_keymap, Keymap, KeymapError, KEYMAPS};
+pub use scancode::{CAPS_LOCK, NUM_LOCK, SCROLL_LOCK};
+pub use scancode::{Action, KeyPressed, KeyState, KeyType, Keyboard, Navigation};
+
+const KEYBOARD_IRQ: u8 = 1;
+
+// Keyboard commands
+const CMD_SET_LEDS: u8 = 0xED;
+const CMD_SET_TYPEMATIC: u8 = 0xF3;
+const CMD_ENABLE_SCANNING: u8 = 0xF4;
+const CMD_RESET: u8 = 0xFF;
+
+const SELF_TEST_PASSED: u8 = 0xAA;
+// The self-test after a reset may take several hundred milliseconds
+const RESET_TIMEOUT_MS: u64 = 1000;
+
+pub const DEFAULT_TYPEMATIC_DELAY_MS: u32 = 500;
+pub const DEFAULT_TYPEMATIC_RATE: u8 = 0x04;     // about 20 keys per second
+
+pub static KEYBOARD: IrqSpinLock<Keyboard> = IrqSpinLock::new(Keyboard::new());
+
+// Echo keys on the console while nobody else reads them
+static ECHO: AtomicBool = AtomicBool::new(true);
+// A lock key changed, the LEDs need an update
+static LEDS_CHANGED: AtomicBool = AtomicBool::new(false);
+static KEYBOARD_WORK: Work = Work::new("keyboard", keyboard_bottom_half);
+
+/// Attaches the keyboard to its IRQ, then resets it and sets the repeat
+/// rate. The PS/2 controller must be initialized.
+pub fn init() -> Result<(), Ps2Error> {
+    attach();
+    reset()?;
+    set_typematic(DEFAULT_TYPEMATIC_DELAY_MS, DEFAULT_TYPEMATIC_RATE)?;
+    ps2::send(Port::First, CMD_ENABLE_SCANNING)
+}
+
+/// Attaches the keyboard to its IRQ as the firmware left it, without sending
+/// it anything. For when the PS/2 controller couldn't be initialized.
+pub fn attach() {
+    register_irq_handler(KEYBOARD_IRQ, "keyboard", keyboard_irq)
+        .expect("keyboard IRQ line already taken");
+    hotkeys::init();
+    ps2::set_receiver(Port::First, handle_scancode);
+}
+
+fn keyboard_irq(_frame: &InterruptFrame) -> IrqReturn {
+    // Nothing to read, or the byte belongs to the mouse: not our interrupt
+    if ps2::pending() != Some(Port::First) {
+        return IrqReturn::NotHandled;
+    }
+
+    // Reading the scancode also acknowledges the keyboard
+    let Some((_, scancode)) = ps2::poll() else {
+        return IrqReturn::NotHandled;
+    };
+
+    handle_scancode(scancode);
+    IrqReturn::Handled
+}
+
+// Decodes a scancode into the event queue
+fn handle_scancode(scancode: u8) {
+    let event = {
+        let mut keyboard = KEYBOARD.lock();
+        let Some(key_info) = keyboard.scan(scancode) else {
+            return;
+        };
+        KeyEvent {
+            key: key_info.key,
+            state: key_info.state,
+            code: key_info.code,
+            modifiers: key_info.modifiers,
+        }
+    };
+
+    if hotkeys::trigger(&event) {
+        workqueue::schedule(&KEYBOARD_WORK);
+        return;
+    }
+
+    let lock_key = matches!(
+        event.key,
+        KeyType::Action(Action::CapsLock | Action::NumLock | Action::ScrollLock)
+    );
+    if lock_key && event.is_pressed() {
+        LEDS_CHANGED.store(true, Ordering::Relaxed);
+    }
+
+    events::push(event);
+    workqueue::schedule(&KEYBOARD_WORK);
+}
+
+/// Turns the echo of typed keys on the console on or off. Code reading the
+/// keyboard turns it off, otherwise the echo takes the events.
+pub fn set_echo(enabled: bool) {
+    ECHO.store(enabled, Ordering::Relaxed);
+}
+
+/// Reads a scancode without relying on the IRQ, for code that runs with
+/// interrupts disabled (e.g. the debugger).
+pub fn poll_scancode() -> Option<u8> {
+    match ps2::poll() {
+        Some((Port::First, scancode)) => Some(scancode),
+        // Mouse byte, not ours
+        _ => None,
+    }
+}
+
+/// Resets the keyboard and waits for its self-test. LEDs go off and the
+/// keyboard falls back to its default settings.
+pub fn reset() -> Result<(), Ps2Error> {
+    ps2::send(Port::First, CMD_RESET)?;
+    match ps2::read_answer(Port::First, RESET_TIMEOUT_MS)? {
+        SELF_TEST_PASSED => Ok(()),
+        answer => Err(Ps2Error::DeviceTest(Port::First, answer)),
+    }
+}
+
+/// Sets the key repeat: `delay_ms` before repeating (250 to 1000, in steps
+/// of 250) and `rate` from 0 (30 keys per second) to 31 (2 per second).
+pub fn set_typematic(delay_ms: u32, rate: u8) -> Result<(), Ps2Error> {
+    let delay = (delay_ms / 250).clamp(1, 4) as u8 - 1;
+    ps2::send_command(Port::First, CMD_SET_TYPEMATIC, &[delay << 5 | rate & 0x1F])
+}
+
+/// Lights the keyboard LEDs, `leds` being a mask of `SCROLL_LOCK`,
+/// `NUM_LOCK` and `CAPS_LOCK`.
+pub fn set_leds(leds: u8) -> Result<(), Ps2Error> {
+    ps2::send_command(Port::First, CMD_SET_LEDS, &[leds & (SCROLL_LOCK | NUM_LOCK | CAPS_LOCK)])
+}
+
+// Runs hotkey actions and updates the LEDs, which take too long for the IRQ
+// handler, and echoes
+fn keyboard_bottom_half() {
+    hotkeys::run_pending();
+
+    if LEDS_CHANGED.swap(false, Ordering::Relaxed) {
+        // Not fatal, the lock state is right even if the LEDs aren't
+        let leds = KEYBOARD.lock().leds();
+        let _ = set_leds(leds);
+    }
+
+    if ECHO.load(Ordering::Relaxed) {
+        while let Some(event) = try_read_event() {
+            echo(event);
+        }
+    }
+}
+
+fn echo(event: KeyEvent) {
+    let mut writer = writer();
+    match event.char() {
+        Some(b'\n') => writer.new_line(),
+        Some(b'\t') => writer.write("    "),
+        Some(BACKSPACE) => writer.delete_last_char(),
+        Some(chr) => writer.write_byte(chr),
+        None => {}
+    }
+}
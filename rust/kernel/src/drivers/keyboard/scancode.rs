@@ -0,0 +1,399 @@
+use super::keycode::{KeyCode, Modifiers};
+use super::keymap::keymap;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum KeyState {
+    Pressed,
+    Released,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Action {
+    Backspace,
+    Space,
+    Enter,
+    Tab,
+    Escape,
+    Shift,
+    Ctrl,
+    Alt,
+    CapsLock,
+    NumLock,
+    ScrollLock,
+    RightCtrl,
+    AltGr,                      // right alt
+    Super,
+    Menu,
+    PrintScreen,
+    SysRq,                      // Print Screen with Alt held
+    Pause,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Navigation {
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum KeyType {
+    Character(u8),
+    Number(u8),
+    Action(Action),
+    Navigation(Navigation),
+    Function(u8),               // F1-F12
+    Undefined(u8)
+}
+
+impl KeyType {
+    pub fn is_printable(&self) -> bool {
+        match self {
+            Self::Character(_) => true,
+            Self::Number(_) => true,
+            Self::Action(_) => false,
+            Self::Navigation(_) => false,
+            Self::Function(_) => false,
+            Self::Undefined(_) => false
+        }
+    }
+
+    pub fn print(&self) -> Option<u8> {
+        match *self {
+            Self::Character(c) => Some(c),
+            Self::Number(n) => Some(n),
+            _ => None
+        }
+    }
+}
+
+#[derive(PartialEq)]
+pub struct KeyPressed {
+    pub key: KeyType,
+    pub state: KeyState,
+    pub code: KeyCode,
+    /// Modifiers held, this key included
+    pub modifiers: Modifiers,
+}
+
+// Keypad keys 0x47-0x53 without Num Lock
+const KEYPAD_NAVIGATION: [Option<Navigation>; 13] = [
+    Some(Navigation::Home), Some(Navigation::Up), Some(Navigation::PageUp), None,
+    Some(Navigation::Left), None, Some(Navigation::Right), None,
+    Some(Navigation::End), Some(Navigation::Down), Some(Navigation::PageDown),
+    Some(Navigation::Insert), Some(Navigation::Delete),
+];
+// And with Num Lock
+const KEYPAD_NUMLOCK: [u8; 13] = *b"789-456+1230.";
+
+// Lock keys, in the bit layout of the LED byte of the 0xED command
+pub const SCROLL_LOCK: u8 = 1 << 0;
+pub const NUM_LOCK: u8 = 1 << 1;
+pub const CAPS_LOCK: u8 = 1 << 2;
+
+// Pause sends E1 1D 45 E1 9D C5 and no release
+const PAUSE_SEQUENCE_LEN: u8 = 6;
+
+// Multi-byte sequence being received
+#[derive(PartialEq, Clone, Copy)]
+enum Prefix {
+    None,
+    Extended,                   // after 0xE0
+    Pause(u8),                  // bytes of the 0xE1 sequence seen so far
+}
+
+pub struct Keyboard {
+    modifiers: Modifiers,
+    // Lock keys toggled on, and the ones held down (so key repeat doesn't
+    // toggle them again)
+    locks: u8,
+    held_locks: u8,
+    prefix: Prefix,
+}
+
+impl Keyboard {
+    pub const fn new() -> Keyboard {
+        Keyboard {
+            modifiers: Modifiers::NONE,
+            locks: 0,
+            held_locks: 0,
+            prefix: Prefix::None,
+        }
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    pub fn caps_lock(&self) -> bool {
+        self.locks & CAPS_LOCK != 0
+    }
+
+    pub fn num_lock(&self) -> bool {
+        self.locks & NUM_LOCK != 0
+    }
+
+    pub fn scroll_lock(&self) -> bool {
+        self.locks & SCROLL_LOCK != 0
+    }
+
+    /// Lock state as the LED byte of the keyboard.
+    pub fn leds(&self) -> u8 {
+        self.locks
+    }
+
+    fn toggle(&mut self, lock: u8, pressed: bool) {
+        if pressed && self.held_locks & lock == 0 {
+            self.locks ^= lock;
+        }
+        if pressed {
+            self.held_locks |= lock;
+        } else {
+            self.held_locks &= !lock;
+        }
+    }
+
+    pub fn is_number(&self, ascii_code: u8) -> bool {
+        matches!(ascii_code, 0x30..=0x39)
+    }
+
+    pub fn is_alphabet(ascii_code: u8) -> bool {
+        matches!(ascii_code, 0x61..=0x7A | 0x41..=0x5A)
+    }
+
+    pub fn is_printable(&self, ascii_code: u8) -> bool {
+       Keyboard::is_alphabet(ascii_code) || self.is_number(ascii_code)   
+    }
+
+    /// Feeds one byte from the keyboard. Returns `None` while in the middle
+    /// of a multi-byte sequence.
+    pub fn scan(&mut self, byte: u8) -> Option<KeyPressed> {
+        match (self.prefix, byte) {
+            (Prefix::None, 0xE0) => {
+                self.prefix = Prefix::Extended;
+                return None;
+            },
+            (Prefix::None, 0xE1) => {
+                self.prefix = Prefix::Pause(1);
+                return None;
+            },
+            (Prefix::Pause(seen), _) => {
+                if seen + 1 < PAUSE_SEQUENCE_LEN {
+                    self.prefix = Prefix::Pause(seen + 1);
+                    return None;
+                }
+                self.prefix = Prefix::None;
+                return Some(KeyPressed {
+                    key: KeyType::Action(Action::Pause),
+                    state: KeyState::Pressed,
+                    code: KeyCode::PAUSE,
+                    modifiers: self.modifiers,
+                });
+            },
+            _ => {}
+        }
+
+        let state = if byte & 0x80 != 0 {
+            KeyState::Released
+        } else {
+            KeyState::Pressed
+        };
+        let scancode = byte & 0x7F;
+
+        let (key, code) = if self.prefix == Prefix::Extended {
+            self.prefix = Prefix::None;
+            let key = self.scan_extended(scancode, state == KeyState::Pressed)?;
+            (key, KeyCode::extended(scancode))
+        } else {
+            (self.scan_main(scancode, state == KeyState::Pressed), KeyCode(scancode))
+        };
+
+        Some(KeyPressed {
+            key,
+            state,
+            code,
+            modifiers: self.modifiers,
+        })
+    }
+
+    fn scan_main(&mut self, scancode: u8, pressed: bool) -> KeyType {
+        match scancode {
+            0x01 => KeyType::Action(Action::Escape),
+            0x0E => KeyType::Action(Action::Backspace),
+            0x0F => KeyType::Action(Action::Tab),
+            0x1C => KeyType::Action(Action::Enter),
+            0x1D => {
+                self.modifiers.set(Modifiers::LEFT_CTRL, pressed);
+                KeyType::Action(Action::Ctrl)
+            },
+            0x2A => {
+                self.modifiers.set(Modifiers::LEFT_SHIFT, pressed);
+                KeyType::Action(Action::Shift)
+            },
+            0x36 => {
+                self.modifiers.set(Modifiers::RIGHT_SHIFT, pressed);
+                KeyType::Action(Action::Shift)
+            },
+            0x37 => KeyType::Character(b'*'),                       // keypad
+            0x38 => {
+                self.modifiers.set(Modifiers::LEFT_ALT, pressed);
+                KeyType::Action(Action::Alt)
+            },
+            0x39 => KeyType::Action(Action::Space),
+            0x3A => {
+                self.toggle(CAPS_LOCK, pressed);
+                KeyType::Action(Action::CapsLock)
+            },
+            0x3B..=0x44 => KeyType::Function(scancode - 0x3A),     // F1-F10
+            0x45 => {
+                self.toggle(NUM_LOCK, pressed);
+                KeyType::Action(Action::NumLock)
+            },
+            0x46 => {
+                self.toggle(SCROLL_LOCK, pressed);
+                KeyType::Action(Action::ScrollLock)
+            },
+            0x47..=0x53 => self.keypad(scancode),
+            0x54 => KeyType::Action(Action::SysRq),
+            0x57 | 0x58 => KeyType::Function(scancode - 0x4C),     // F11-F12
+            _ => self.translate(scancode),
+        }
+    }
+
+    // Keys after 0xE0, `None` for the fake shifts sent around some of them
+    fn scan_extended(&mut self, scancode: u8, pressed: bool) -> Option<KeyType> {
+        let key = match scancode {
+            0x1C => KeyType::Action(Action::Enter),                 // keypad enter
+            0x1D => {
+                self.modifiers.set(Modifiers::RIGHT_CTRL, pressed);
+                KeyType::Action(Action::RightCtrl)
+            },
+            0x2A | 0x36 => return None,
+            0x35 => KeyType::Character(b'/'),                       // keypad slash
+            0x37 => KeyType::Action(Action::PrintScreen),
+            0x38 => {
+                self.modifiers.set(Modifiers::RIGHT_ALT, pressed);
+                KeyType::Action(Action::AltGr)
+            },
+            0x47 => KeyType::Navigation(Navigation::Home),
+            0x48 => KeyType::Navigation(Navigation::Up),
+            0x49 => KeyType::Navigation(Navigation::PageUp),
+            0x4B => KeyType::Navigation(Navigation::Left),
+            0x4D => KeyType::Navigation(Navigation::Right),
+            0x4F => KeyType::Navigation(Navigation::End),
+            0x50 => KeyType::Navigation(Navigation::Down),
+            0x51 => KeyType::Navigation(Navigation::PageDown),
+            0x52 => KeyType::Navigation(Navigation::Insert),
+            0x53 => KeyType::Navigation(Navigation::Delete),
+            0x5B => {
+                self.modifiers.set(Modifiers::LEFT_SUPER, pressed);
+                KeyType::Action(Action::Super)
+            },
+            0x5C => {
+                self.modifiers.set(Modifiers::RIGHT_SUPER, pressed);
+                KeyType::Action(Action::Super)
+            },
+            0x5D => KeyType::Action(Action::Menu),
+            _ => KeyType::Undefined(scancode),
+        };
+        Some(key)
+    }
+
+    // Keypad without the 0xE0 prefix: digits with Num Lock, navigation keys
+    // otherwise. Shift inverts Num Lock, like on other systems.
+    fn keypad(&self, scancode: u8) -> KeyType {
+        let index = (scancode - 0x47) as usize;
+        let chr = KEYPAD_NUMLOCK[index];
+        if !self.is_number(chr) && chr != b'.' {
+            return KeyType::Character(chr);
+        }
+
+        if self.num_lock() != self.modifiers.shift() {
+            if self.is_number(chr) { KeyType::Number(chr) } else { KeyType::Character(chr) }
+        } else {
+            match KEYPAD_NAVIGATION[index] {
+                Some(navigation) => KeyType::Navigation(navigation),
+                None => KeyType::Undefined(scancode),
+            }
+        }
+    }
+
+    // Character of a main block key in the current layout
+    fn translate(&self, scancode: u8) -> KeyType {
+        let keymap = keymap();
+        let shift = self.modifiers.shift() != (self.caps_lock() && keymap.is_letter(scancode));
+        match keymap.translate(scancode, shift, self.modifiers.altgr()) {
+            0 => KeyType::Undefined(scancode),
+            chr if self.is_number(chr) => KeyType::Number(chr),
+            chr => KeyType::Character(chr),
+        }
+    }
+/*
+    pub fn scancode_to_ascii(&mut self, mut scancode: u8) -> KeyPressed {
+        let state = if scancode & 0x80 != 0 {
+            scancode -= 0x80;
+            KeyState::Released
+        } else {
+            KeyState::Pressed
+        };
+
+        let mut chr = match scancode {
+            0x0B => 0x30,                       // 0
+            0x02..=0x0A => scancode + 0x2F,     // 1-9
+            0x1E => 0x61,                       // A
+            0x30 => 0x62,                       // B
+            0x2E => 0x63,                       // C
+            0x20 => 0x64,                       // D
+            0x12 => 0x65,                       // E
+            0x21..=0x23 => scancode + 0x45,     // F-H
+            0x17 => 0x69,                       // I
+            0x24..=0x26 => scancode + 0x46,     // J-L
+            0x32 => 0x6D,                       // M
+            0x31 => 0x6E,                       // N
+            0x18 => 0x6F,                       // O
+            0x19 => 0x70,                       // P
+            0x10 => 0x71,                       // Q
+            0x13 => 0x72,                       // R
+            0x1F => 0x73,                       // S
+            0x14 => 0x74,                       // T
+            0x16 => 0x75,                       // U
+            0x2F => 0x76,                       // V
+            0x11 => 0x77,                       // W
+            0x2D => 0x78,                       // X
+            0x15 => 0x79,                       // Y
+            0x2C => 0x7A,                       // Z
+            0x36 => {
+                self.shift_enabled = state == KeyState::Pressed;
+                0x0E
+            },                                  // right shift
+            0x2A => {
+                self.shift_enabled = state == KeyState::Pressed;
+                0x0E
+            },                                  // left shift
+            0x0E => 0x7F                        // Delete
+            _ => scancode
+        };
+
+        if self.shift_enabled && Keyboard::is_alphabet(chr) {
+            chr -= 0x20;
+        }
+
+        KeyPressed {
+            chr,
+            state
+        }
+    } */
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,3 @@
+pub mod keyboard;
+pub mod mouse;
+pub mod ps2;
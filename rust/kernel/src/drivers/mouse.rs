@@ -0,0 +1,300 @@
+// PS/2 mouse on the second port of the 8042, IRQ 12.
+//
+// A standard mouse sends 3-byte packets: buttons, sign and overflow bits,
+// then X and Y movement. IntelliMouse extensions, unlocked by magic sample
+// rate sequences, add a fourth byte with the wheel and buttons 4 and 5. Bit 3
+// of the first byte is always set, which is how a lost byte is noticed.
+
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
+
+use super::ps2::{self, Port, Ps2Error};
+use crate::display::vga::{VGA_HEIGHT, VGA_WIDTH};
+use crate::display::writer;
+use crate::interrupts::{register_irq_handler, unregister_irq_handler, InterruptFrame, IrqReturn};
+use crate::ring::RingBuffer;
+use crate::sync::IrqSpinLock;
+use crate::workqueue::{self, Work};
+
+const MOUSE_IRQ: u8 = 12;
+
+// Mouse commands
+const CMD_SET_RESOLUTION: u8 = 0xE8;
+const CMD_GET_ID: u8 = 0xF2;
+const CMD_SET_SAMPLE_RATE: u8 = 0xF3;
+const CMD_ENABLE_REPORTING: u8 = 0xF4;
+const CMD_SET_DEFAULTS: u8 = 0xF6;
+const CMD_RESET: u8 = 0xFF;
+
+const SELF_TEST_PASSED: u8 = 0xAA;
+const RESET_TIMEOUT_MS: u64 = 1000;
+const ANSWER_TIMEOUT_MS: u64 = 100;
+
+// Device IDs
+const ID_WHEEL: u8 = 3;
+const ID_FIVE_BUTTONS: u8 = 4;
+
+pub const DEFAULT_SAMPLE_RATE: u8 = 100;
+// 4 counts per millimeter
+pub const DEFAULT_RESOLUTION: u8 = 2;
+
+// First byte of a packet
+const PACKET_BUTTONS: u8 = 0x07;
+const PACKET_ALWAYS_ONE: u8 = 1 << 3;
+const PACKET_X_SIGN: u8 = 1 << 4;
+const PACKET_Y_SIGN: u8 = 1 << 5;
+const PACKET_X_OVERFLOW: u8 = 1 << 6;
+const PACKET_Y_OVERFLOW: u8 = 1 << 7;
+
+// Fourth byte of a 5-button mouse
+const EXTRA_WHEEL: u8 = 0x0F;
+const EXTRA_BUTTON_4: u8 = 1 << 4;
+const EXTRA_BUTTON_5: u8 = 1 << 5;
+
+pub const BUTTON_LEFT: u8 = 1 << 0;
+pub const BUTTON_RIGHT: u8 = 1 << 1;
+pub const BUTTON_MIDDLE: u8 = 1 << 2;
+pub const BUTTON_4: u8 = 1 << 3;
+pub const BUTTON_5: u8 = 1 << 4;
+
+// Movement counts per text cell of the cursor
+const COUNTS_PER_COLUMN: i32 = 8;
+const COUNTS_PER_ROW: i32 = 16;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum MouseKind {
+    Standard,
+    /// IntelliMouse with a scroll wheel
+    Wheel,
+    /// IntelliMouse Explorer, scroll wheel and buttons 4 and 5
+    FiveButtons,
+}
+
+impl MouseKind {
+    fn packet_len(self) -> usize {
+        match self {
+            MouseKind::Standard => 3,
+            MouseKind::Wheel | MouseKind::FiveButtons => 4,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct MouseEvent {
+    /// Movement in counts, right and down are positive
+    pub dx: i16,
+    pub dy: i16,
+    /// Wheel steps, down is positive
+    pub wheel: i8,
+    /// Buttons held, `BUTTON_*` bits
+    pub buttons: u8,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MouseStats {
+    pub packets: u64,
+    /// Bytes dropped while looking for the start of a packet, bit 3 clear
+    pub sync_errors: u64,
+    /// Packets dropped because a movement overflowed
+    pub overflows: u64,
+    /// Events lost because the queue was full
+    pub dropped: u64,
+}
+
+struct Packet {
+    bytes: [u8; 4],
+    len: usize,
+}
+
+static KIND: IrqSpinLock<MouseKind> = IrqSpinLock::new(MouseKind::Standard);
+static PACKET: IrqSpinLock<Packet> = IrqSpinLock::new(Packet { bytes: [0; 4], len: 0 });
+static EVENTS: RingBuffer<MouseEvent, 64> = RingBuffer::new();
+
+static PACKETS: AtomicU64 = AtomicU64::new(0);
+static SYNC_ERRORS: AtomicU64 = AtomicU64::new(0);
+static OVERFLOWS: AtomicU64 = AtomicU64::new(0);
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+// Cursor position in movement counts, drawn by the bottom half
+static CURSOR_X: AtomicI32 = AtomicI32::new(0);
+static CURSOR_Y: AtomicI32 = AtomicI32::new(0);
+static CURSOR_VISIBLE: AtomicBool = AtomicBool::new(true);
+static CURSOR_WORK: Work = Work::new("mouse cursor", draw_cursor);
+
+/// Resets the mouse, enables the extensions it has and starts reporting.
+/// The PS/2 controller must be initialized.
+pub fn init() -> Result<MouseKind, Ps2Error> {
+    if !ps2::has_port(Port::Second) {
+        return Err(Ps2Error::NoPort);
+    }
+
+    ps2::send(Port::Second, CMD_RESET)?;
+    match ps2::read_answer(Port::Second, RESET_TIMEOUT_MS)? {
+        SELF_TEST_PASSED => {}
+        answer => return Err(Ps2Error::DeviceTest(Port::Second, answer)),
+    }
+    // Device ID, 0 after a reset
+    ps2::read_answer(Port::Second, ANSWER_TIMEOUT_MS)?;
+    ps2::send(Port::Second, CMD_SET_DEFAULTS)?;
+
+    let kind = detect()?;
+    *KIND.lock() = kind;
+    set_sample_rate(DEFAULT_SAMPLE_RATE)?;
+    ps2::send_command(Port::Second, CMD_SET_RESOLUTION, &[DEFAULT_RESOLUTION])?;
+
+    CURSOR_X.store(VGA_WIDTH as i32 / 2 * COUNTS_PER_COLUMN, Ordering::Relaxed);
+    CURSOR_Y.store(VGA_HEIGHT as i32 / 2 * COUNTS_PER_ROW, Ordering::Relaxed);
+    register_irq_handler(MOUSE_IRQ, "mouse", mouse_irq).map_err(Ps2Error::Irq)?;
+    ps2::set_receiver(Port::Second, handle_byte);
+    if let Err(e) = ps2::send(Port::Second, CMD_ENABLE_REPORTING) {
+        ps2::clear_receiver(Port::Second);
+        let _ = unregister_irq_handler(MOUSE_IRQ, "mouse");
+        return Err(e);
+    }
+    workqueue::schedule(&CURSOR_WORK);
+    Ok(kind)
+}
+
+fn read_id() -> Result<u8, Ps2Error> {
+    ps2::send(Port::Second, CMD_GET_ID)?;
+    ps2::read_answer(Port::Second, ANSWER_TIMEOUT_MS)
+}
+
+pub fn set_sample_rate(rate: u8) -> Result<(), Ps2Error> {
+    ps2::send_command(Port::Second, CMD_SET_SAMPLE_RATE, &[rate])
+}
+
+// The IntelliMouse knocks: each sample rate sequence unlocks an extension
+// and changes the device ID
+fn detect() -> Result<MouseKind, Ps2Error> {
+    [200, 100, 80].into_iter().try_for_each(set_sample_rate)?;
+    if read_id()? != ID_WHEEL {
+        return Ok(MouseKind::Standard);
+    }
+
+    [200, 200, 80].into_iter().try_for_each(set_sample_rate)?;
+    if read_id()? != ID_FIVE_BUTTONS {
+        return Ok(MouseKind::Wheel);
+    }
+    Ok(MouseKind::FiveButtons)
+}
+
+pub fn kind() -> MouseKind {
+    *KIND.lock()
+}
+
+fn mouse_irq(_frame: &InterruptFrame) -> IrqReturn {
+    // Nothing to read, or the byte belongs to the keyboard: not our interrupt
+    if ps2::pending() != Some(Port::Second) {
+        return IrqReturn::NotHandled;
+    }
+
+    match ps2::poll() {
+        Some((_, byte)) => {
+            handle_byte(byte);
+            IrqReturn::Handled
+        }
+        None => IrqReturn::NotHandled,
+    }
+}
+
+// Adds a byte to the packet being received, decoding it once complete
+fn handle_byte(byte: u8) {
+    let len = kind().packet_len();
+    let mut packet = PACKET.lock();
+
+    // Out of sync, wait for a byte that can start a packet
+    if packet.len == 0 && byte & PACKET_ALWAYS_ONE == 0 {
+        SYNC_ERRORS.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+
+    let index = packet.len;
+    packet.bytes[index] = byte;
+    packet.len += 1;
+    if packet.len < len {
+        return;
+    }
+
+    packet.len = 0;
+    let bytes = packet.bytes;
+    drop(packet);
+
+    PACKETS.fetch_add(1, Ordering::Relaxed);
+    if bytes[0] & (PACKET_X_OVERFLOW | PACKET_Y_OVERFLOW) != 0 {
+        OVERFLOWS.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+
+    let event = decode(&bytes);
+    if EVENTS.push(event).is_err() {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+    move_cursor(event);
+}
+
+fn decode(bytes: &[u8; 4]) -> MouseEvent {
+    // 9-bit two's complement, the sign is in the first byte
+    let movement = |value: u8, negative: bool| value as i16 - if negative { 0x100 } else { 0 };
+    let dx = movement(bytes[1], bytes[0] & PACKET_X_SIGN != 0);
+    let dy = movement(bytes[2], bytes[0] & PACKET_Y_SIGN != 0);
+    let mut buttons = bytes[0] & PACKET_BUTTONS;
+
+    let wheel = match kind() {
+        MouseKind::Standard => 0,
+        MouseKind::Wheel => bytes[3] as i8,
+        MouseKind::FiveButtons => {
+            if bytes[3] & EXTRA_BUTTON_4 != 0 {
+                buttons |= BUTTON_4;
+            }
+            if bytes[3] & EXTRA_BUTTON_5 != 0 {
+                buttons |= BUTTON_5;
+            }
+            // Sign extend the 4-bit value
+            ((bytes[3] & EXTRA_WHEEL) << 4) as i8 >> 4
+        }
+    };
+
+    // The mouse counts up as positive
+    MouseEvent { dx, dy: -dy, wheel, buttons }
+}
+
+fn move_cursor(event: MouseEvent) {
+    let max_x = VGA_WIDTH as i32 * COUNTS_PER_COLUMN - 1;
+    let max_y = VGA_HEIGHT as i32 * COUNTS_PER_ROW - 1;
+    let x = (CURSOR_X.load(Ordering::Relaxed) + event.dx as i32).clamp(0, max_x);
+    let y = (CURSOR_Y.load(Ordering::Relaxed) + event.dy as i32).clamp(0, max_y);
+    CURSOR_X.store(x, Ordering::Relaxed);
+    CURSOR_Y.store(y, Ordering::Relaxed);
+    workqueue::schedule(&CURSOR_WORK);
+}
+
+// Bottom half, the console lock is not for the IRQ handler
+fn draw_cursor() {
+    let position = CURSOR_VISIBLE.load(Ordering::Relaxed).then(cursor_position);
+    writer().set_mouse_cursor(position);
+}
+
+/// Text cell under the cursor, (row, column).
+pub fn cursor_position() -> (usize, usize) {
+    let col = CURSOR_X.load(Ordering::Relaxed) / COUNTS_PER_COLUMN;
+    let row = CURSOR_Y.load(Ordering::Relaxed) / COUNTS_PER_ROW;
+    (row as usize, col as usize)
+}
+
+pub fn show_cursor(visible: bool) {
+    CURSOR_VISIBLE.store(visible, Ordering::Relaxed);
+    workqueue::schedule(&CURSOR_WORK);
+}
+
+pub fn try_read_event() -> Option<MouseEvent> {
+    EVENTS.pop()
+}
+
+pub fn stats() -> MouseStats {
+    MouseStats {
+        packets: PACKETS.load(Ordering::Relaxed),
+        sync_errors: SYNC_ERRORS.load(Ordering::Relaxed),
+        overflows: OVERFLOWS.load(Ordering::Relaxed),
+        dropped: DROPPED.load(Ordering::Relaxed),
+    }
+}
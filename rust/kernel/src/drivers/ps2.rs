@@ -0,0 +1,333 @@
+// 8042 PS/2 controller.
+//
+// The controller has one data port shared by its two device ports: the
+// keyboard on the first one and, on dual-channel controllers, the mouse on
+// the second. Status bit 5 tells which device a byte in the output buffer
+// comes from. Bytes sent to a device are answered by ACK (0xFA), or by Resend
+// (0xFE) when the device wants the byte again.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::interrupts::{without_interrupts, IrqError};
+use crate::io::{inb, outb};
+use crate::sync::IrqSpinLock;
+use crate::time::delay_us;
+
+const DATA_PORT: u16 = 0x60;
+// Status register on read, command register on write
+const STATUS_PORT: u16 = 0x64;
+const COMMAND_PORT: u16 = 0x64;
+
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+const STATUS_AUX_DATA: u8 = 1 << 5;
+
+// Controller commands
+const CMD_READ_CONFIG: u8 = 0x20;
+const CMD_WRITE_CONFIG: u8 = 0x60;
+const CMD_DISABLE_PORT2: u8 = 0xA7;
+const CMD_ENABLE_PORT2: u8 = 0xA8;
+const CMD_TEST_PORT2: u8 = 0xA9;
+const CMD_TEST_CONTROLLER: u8 = 0xAA;
+const CMD_TEST_PORT1: u8 = 0xAB;
+const CMD_DISABLE_PORT1: u8 = 0xAD;
+const CMD_ENABLE_PORT1: u8 = 0xAE;
+const CMD_WRITE_PORT2: u8 = 0xD4;
+const CMD_PULSE_RESET: u8 = 0xFE;
+
+const CONTROLLER_TEST_PASSED: u8 = 0x55;
+const PORT_TEST_PASSED: u8 = 0x00;
+
+// Controller configuration byte
+const CONFIG_PORT1_IRQ: u8 = 1 << 0;
+const CONFIG_PORT2_IRQ: u8 = 1 << 1;
+const CONFIG_PORT1_CLOCK_DISABLED: u8 = 1 << 4;
+const CONFIG_PORT2_CLOCK_DISABLED: u8 = 1 << 5;
+const CONFIG_TRANSLATION: u8 = 1 << 6;
+
+const RESPONSE_ACK: u8 = 0xFA;
+const RESPONSE_RESEND: u8 = 0xFE;
+
+const MAX_RESENDS: usize = 3;
+// The output buffer of a real controller holds a single byte, some emulated
+// ones queue a few
+const MAX_FLUSH: usize = 16;
+// Polling step and limit while waiting on the controller
+const POLL_STEP_US: u64 = 10;
+const TIMEOUT_US: u64 = 100_000;
+
+pub type Receiver = fn(u8);
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Port {
+    First,
+    Second,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Ps2Error {
+    /// The controller or the device didn't answer in time
+    Timeout,
+    /// The device kept asking for the byte again
+    Resend,
+    /// Controller self-test failed, with the answer to the test
+    ControllerTest(u8),
+    /// Self-test of a device failed, with its answer
+    DeviceTest(Port, u8),
+    /// The port is missing or failed its test
+    NoPort,
+    /// The device IRQ handler could not be attached
+    Irq(IrqError),
+}
+
+/// What `init` found.
+#[derive(Clone, Copy, Debug)]
+pub struct Ps2Ports {
+    pub first: bool,
+    pub second: bool,
+}
+
+static PORT1: AtomicBool = AtomicBool::new(false);
+static PORT2: AtomicBool = AtomicBool::new(false);
+
+// Where bytes read while waiting for an answer go, per port
+static RECEIVERS: IrqSpinLock<[Option<Receiver>; 2]> = IrqSpinLock::new([None; 2]);
+
+fn wait_input_empty() -> Result<(), Ps2Error> {
+    for _ in 0..TIMEOUT_US / POLL_STEP_US {
+        if unsafe { inb(STATUS_PORT) } & STATUS_INPUT_FULL == 0 {
+            return Ok(());
+        }
+        delay_us(POLL_STEP_US);
+    }
+    Err(Ps2Error::Timeout)
+}
+
+fn wait_output_full() -> Result<(), Ps2Error> {
+    for _ in 0..TIMEOUT_US / POLL_STEP_US {
+        if unsafe { inb(STATUS_PORT) } & STATUS_OUTPUT_FULL != 0 {
+            return Ok(());
+        }
+        delay_us(POLL_STEP_US);
+    }
+    Err(Ps2Error::Timeout)
+}
+
+fn write_command(command: u8) -> Result<(), Ps2Error> {
+    wait_input_empty()?;
+    unsafe { outb(COMMAND_PORT, command) };
+    Ok(())
+}
+
+fn write_data(data: u8) -> Result<(), Ps2Error> {
+    wait_input_empty()?;
+    unsafe { outb(DATA_PORT, data) };
+    Ok(())
+}
+
+// Waits for a byte from the controller or either device
+fn read_data() -> Result<u8, Ps2Error> {
+    wait_output_full()?;
+    Ok(unsafe { inb(DATA_PORT) })
+}
+
+// Controller command answered by one byte
+fn command_response(command: u8) -> Result<u8, Ps2Error> {
+    write_command(command)?;
+    read_data()
+}
+
+fn read_config() -> Result<u8, Ps2Error> {
+    command_response(CMD_READ_CONFIG)
+}
+
+fn write_config(config: u8) -> Result<(), Ps2Error> {
+    write_command(CMD_WRITE_CONFIG)?;
+    write_data(config)
+}
+
+fn flush_output() {
+    for _ in 0..MAX_FLUSH {
+        if unsafe { inb(STATUS_PORT) } & STATUS_OUTPUT_FULL == 0 {
+            break;
+        }
+        unsafe { inb(DATA_PORT) };
+    }
+}
+
+/// Sets the controller up from scratch: self-tests, port detection and
+/// translation of the keyboard to scancode set 1. Must run before the device
+/// drivers, with their IRQs not yet expected to fire. On failure the
+/// controller is left configured as the firmware had it.
+pub fn init() -> Result<Ps2Ports, Ps2Error> {
+    without_interrupts(|| {
+        flush_output();
+        let firmware_config = read_config()?;
+        let result = setup();
+        if result.is_err() {
+            restore(firmware_config);
+        }
+        result
+    })
+}
+
+// Puts the controller back as the firmware set it up, ports included
+fn restore(config: u8) {
+    let _ = write_config(config);
+    if config & CONFIG_PORT1_CLOCK_DISABLED == 0 {
+        let _ = write_command(CMD_ENABLE_PORT1);
+    }
+    if config & CONFIG_PORT2_CLOCK_DISABLED == 0 {
+        let _ = write_command(CMD_ENABLE_PORT2);
+    }
+    flush_output();
+}
+
+fn setup() -> Result<Ps2Ports, Ps2Error> {
+    // Keep the devices quiet during setup
+    write_command(CMD_DISABLE_PORT1)?;
+    write_command(CMD_DISABLE_PORT2)?;
+    flush_output();
+
+    let mut config = read_config()?;
+    config &= !(CONFIG_PORT1_IRQ | CONFIG_PORT2_IRQ | CONFIG_TRANSLATION);
+    write_config(config)?;
+
+    match command_response(CMD_TEST_CONTROLLER)? {
+        CONTROLLER_TEST_PASSED => {}
+        answer => return Err(Ps2Error::ControllerTest(answer)),
+    }
+    // The self-test may reset the controller
+    write_config(config)?;
+
+    // Enabling the second port only starts its clock on dual-channel
+    // controllers
+    let mut dual = false;
+    if config & CONFIG_PORT2_CLOCK_DISABLED != 0 {
+        write_command(CMD_ENABLE_PORT2)?;
+        dual = read_config()? & CONFIG_PORT2_CLOCK_DISABLED == 0;
+        write_command(CMD_DISABLE_PORT2)?;
+    }
+
+    let first = command_response(CMD_TEST_PORT1)? == PORT_TEST_PASSED;
+    let second = dual && command_response(CMD_TEST_PORT2)? == PORT_TEST_PASSED;
+    if !first && !second {
+        return Err(Ps2Error::NoPort);
+    }
+
+    if first {
+        write_command(CMD_ENABLE_PORT1)?;
+        config |= CONFIG_PORT1_IRQ | CONFIG_TRANSLATION;
+    }
+    if second {
+        write_command(CMD_ENABLE_PORT2)?;
+        config |= CONFIG_PORT2_IRQ;
+    }
+    write_config(config)?;
+    flush_output();
+
+    PORT1.store(first, Ordering::Relaxed);
+    PORT2.store(second, Ordering::Relaxed);
+    Ok(Ps2Ports { first, second })
+}
+
+/// True if the port passed its test in `init`.
+pub fn has_port(port: Port) -> bool {
+    match port {
+        Port::First => PORT1.load(Ordering::Relaxed),
+        Port::Second => PORT2.load(Ordering::Relaxed),
+    }
+}
+
+/// Port of the byte waiting in the output buffer, if any. Doesn't read it.
+pub fn pending() -> Option<Port> {
+    let status = unsafe { inb(STATUS_PORT) };
+    if status & STATUS_OUTPUT_FULL == 0 {
+        None
+    } else if status & STATUS_AUX_DATA != 0 {
+        Some(Port::Second)
+    } else {
+        Some(Port::First)
+    }
+}
+
+/// Reads the byte waiting in the output buffer, without waiting.
+pub fn poll() -> Option<(Port, u8)> {
+    let port = pending()?;
+    Some((port, unsafe { inb(DATA_PORT) }))
+}
+
+/// Sets where bytes of `port` go when they arrive while a command is waiting
+/// for its answer, e.g. keys typed meanwhile. Without a receiver they're lost.
+pub fn set_receiver(port: Port, receiver: Receiver) {
+    RECEIVERS.lock()[port as usize] = Some(receiver);
+}
+
+pub fn clear_receiver(port: Port) {
+    RECEIVERS.lock()[port as usize] = None;
+}
+
+fn deliver(port: Port, byte: u8) {
+    let receiver = RECEIVERS.lock()[port as usize];
+    if let Some(receiver) = receiver {
+        receiver(byte);
+    }
+}
+
+// Waits for the answer of the device on `port`
+fn wait_answer(port: Port, timeout_us: u64) -> Result<u8, Ps2Error> {
+    for _ in 0..timeout_us / POLL_STEP_US {
+        match poll() {
+            Some((from, byte)) if from == port => return Ok(byte),
+            Some((from, byte)) => deliver(from, byte),
+            None => delay_us(POLL_STEP_US),
+        }
+    }
+    Err(Ps2Error::Timeout)
+}
+
+/// Sends a byte to the device on `port`, again while it answers Resend.
+/// Runs with interrupts disabled so the IRQ handlers don't take the ACK.
+pub fn send(port: Port, byte: u8) -> Result<(), Ps2Error> {
+    if !has_port(port) {
+        return Err(Ps2Error::NoPort);
+    }
+
+    without_interrupts(|| {
+        for _ in 0..=MAX_RESENDS {
+            if port == Port::Second {
+                write_command(CMD_WRITE_PORT2)?;
+            }
+            write_data(byte)?;
+
+            loop {
+                match wait_answer(port, TIMEOUT_US)? {
+                    RESPONSE_ACK => return Ok(()),
+                    RESPONSE_RESEND => break,
+                    // Data sent before the device saw the command
+                    other => deliver(port, other),
+                }
+            }
+        }
+        Err(Ps2Error::Resend)
+    })
+}
+
+/// Sends a command and its parameter bytes to the device on `port`.
+pub fn send_command(port: Port, command: u8, params: &[u8]) -> Result<(), Ps2Error> {
+    send(port, command)?;
+    params.iter().try_for_each(|&param| send(port, param))
+}
+
+/// Waits up to `timeout_ms` for a byte from the device on `port`, after a
+/// command that answers with data.
+pub fn read_answer(port: Port, timeout_ms: u64) -> Result<u8, Ps2Error> {
+    without_interrupts(|| wait_answer(port, timeout_ms * 1000))
+}
+
+/// Pulses the CPU reset line, wired to the controller on PCs. Returns if the
+/// machine didn't reset.
+pub fn pulse_reset() {
+    let _ = write_command(CMD_PULSE_RESET);
+    delay_us(TIMEOUT_US);
+}
@@ -0,0 +1,195 @@
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::acpi::madt::{
+    Madt, ALL_PROCESSORS, INTI_ACTIVE_LOW, INTI_LEVEL_TRIGGERED, INTI_POLARITY_MASK,
+    INTI_TRIGGER_MASK,
+};
+use crate::acpi::AcpiError;
+use crate::cpu::{cpuid, rdmsr, wrmsr, IA32_APIC_BASE};
+use crate::paging::map_mmio;
+
+/// Vector the local APIC uses for spurious interrupts, they must not be acknowledged.
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+// IA32_APIC_BASE bits
+const APIC_BASE_X2APIC: u64 = 1 << 10;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+// Register offsets in the xAPIC MMIO page, x2APIC uses MSR 0x800 + (offset >> 4)
+const REG_ID: u32 = 0x20;
+const REG_TPR: u32 = 0x80;
+const REG_EOI: u32 = 0xB0;
+const REG_SVR: u32 = 0xF0;
+const REG_LVT_TIMER: u32 = 0x320;
+const REG_LVT_PERF: u32 = 0x340;
+const REG_LVT_LINT0: u32 = 0x350;
+const REG_LVT_LINT1: u32 = 0x360;
+const REG_LVT_ERROR: u32 = 0x370;
+const REG_TIMER_INITIAL_COUNT: u32 = 0x380;
+const REG_TIMER_CURRENT_COUNT: u32 = 0x390;
+const REG_TIMER_DIVIDE: u32 = 0x3E0;
+
+const IA32_TSC_DEADLINE: u32 = 0x6E0;
+
+const X2APIC_MSR_BASE: u32 = 0x800;
+
+const SVR_ENABLE: u32 = 1 << 8;
+const LVT_DELIVERY_NMI: u32 = 0b100 << 8;
+const LVT_ACTIVE_LOW: u32 = 1 << 13;
+const LVT_LEVEL_TRIGGERED: u32 = 1 << 15;
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_TIMER_TSC_DEADLINE: u32 = 0b10 << 17;
+// Divide configuration value for a divisor of 16
+const TIMER_DIVIDE_BY_16: u32 = 0b0011;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ApicError {
+    Unsupported,
+    Acpi(AcpiError),
+    NoIoApic,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static X2APIC: AtomicBool = AtomicBool::new(false);
+static BASE: AtomicU64 = AtomicU64::new(0);
+
+pub fn is_supported() -> bool {
+    cpuid(1).edx & (1 << 9) != 0
+}
+
+/// True if the timer can fire on an absolute TSC value.
+pub fn tsc_deadline_supported() -> bool {
+    cpuid(1).ecx & (1 << 24) != 0
+}
+
+fn x2apic_supported() -> bool {
+    cpuid(1).ecx & (1 << 21) != 0
+}
+
+/// True once the local APIC delivers interrupts instead of the 8259.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+pub fn is_x2apic() -> bool {
+    X2APIC.load(Ordering::Relaxed)
+}
+
+/// Enables the local APIC of the calling CPU, in x2APIC mode when available.
+pub fn init_local(madt: &Madt) {
+    unsafe {
+        let mut base_msr = rdmsr(IA32_APIC_BASE) | APIC_BASE_ENABLE;
+        wrmsr(IA32_APIC_BASE, base_msr);
+
+        if x2apic_supported() {
+            // x2APIC can only be entered from the enabled xAPIC state
+            base_msr |= APIC_BASE_X2APIC;
+            wrmsr(IA32_APIC_BASE, base_msr);
+            X2APIC.store(true, Ordering::Relaxed);
+        } else {
+            let mut address = base_msr & APIC_BASE_ADDRESS_MASK;
+            if address == 0 {
+                address = madt.local_apic_address;
+            }
+            BASE.store(map_mmio(address, 0x1000) as u64, Ordering::Relaxed);
+        }
+
+        // Accept every priority class
+        write(REG_TPR, 0);
+
+        // LINT pins are only used for NMIs described by the firmware
+        write(REG_LVT_LINT0, LVT_MASKED);
+        write(REG_LVT_LINT1, LVT_MASKED);
+        write(REG_LVT_ERROR, LVT_MASKED);
+
+        let apic_id = id();
+        let processor_id = madt.cpus().iter().find(|cpu| cpu.apic_id == apic_id).map(|cpu| cpu.processor_id);
+
+        for nmi in madt.nmis() {
+            if nmi.processor_id != ALL_PROCESSORS && Some(nmi.processor_id) != processor_id {
+                continue;
+            }
+
+            let mut lvt = LVT_DELIVERY_NMI;
+            if nmi.flags & INTI_POLARITY_MASK == INTI_ACTIVE_LOW {
+                lvt |= LVT_ACTIVE_LOW;
+            }
+            if nmi.flags & INTI_TRIGGER_MASK == INTI_LEVEL_TRIGGERED {
+                lvt |= LVT_LEVEL_TRIGGERED;
+            }
+
+            match nmi.lint {
+                0 => write(REG_LVT_LINT0, lvt),
+                1 => write(REG_LVT_LINT1, lvt),
+                _ => {}
+            }
+        }
+
+        // Software enable and spurious vector
+        write(REG_SVR, SVR_ENABLE | SPURIOUS_VECTOR as u32);
+    }
+
+    ENABLED.store(true, Ordering::Release);
+}
+
+/// Local APIC id of the calling CPU.
+pub fn id() -> u32 {
+    let id = unsafe { read(REG_ID) };
+    if is_x2apic() { id } else { id >> 24 }
+}
+
+pub fn eoi() {
+    unsafe { write(REG_EOI, 0) };
+}
+
+/// Sets the timer up to raise `vector` in one-shot mode, counting down the bus
+/// clock divided by 16, or in TSC-deadline mode. It stays idle until armed.
+pub fn init_timer(vector: u8, tsc_deadline: bool) {
+    unsafe {
+        write(REG_TIMER_INITIAL_COUNT, 0);
+        write(REG_TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+        let mode = if tsc_deadline { LVT_TIMER_TSC_DEADLINE } else { 0 };
+        write(REG_LVT_TIMER, mode | vector as u32);
+    }
+}
+
+/// Fires the timer once `count` divided bus clocks elapsed, 0 stops it.
+pub fn set_timer_count(count: u32) {
+    unsafe { write(REG_TIMER_INITIAL_COUNT, count) };
+}
+
+pub fn timer_current_count() -> u32 {
+    unsafe { read(REG_TIMER_CURRENT_COUNT) }
+}
+
+/// Fires the timer when the TSC reaches `deadline`, 0 stops it.
+pub fn set_tsc_deadline(deadline: u64) {
+    unsafe { wrmsr(IA32_TSC_DEADLINE, deadline) };
+}
+
+/// Delivers performance counter overflows as NMIs.
+pub fn set_perf_counter_nmi() {
+    unsafe { write(REG_LVT_PERF, LVT_DELIVERY_NMI) };
+}
+
+unsafe fn read(reg: u32) -> u32 {
+    unsafe {
+        if is_x2apic() {
+            rdmsr(X2APIC_MSR_BASE + (reg >> 4)) as u32
+        } else {
+            read_volatile((BASE.load(Ordering::Relaxed) + reg as u64) as *const u32)
+        }
+    }
+}
+
+unsafe fn write(reg: u32, value: u32) {
+    unsafe {
+        if is_x2apic() {
+            wrmsr(X2APIC_MSR_BASE + (reg >> 4), value as u64);
+        } else {
+            write_volatile((BASE.load(Ordering::Relaxed) + reg as u64) as *mut u32, value);
+        }
+    }
+}
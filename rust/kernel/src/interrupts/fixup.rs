@@ -0,0 +1,55 @@
+// Exception fixup table.
+//
+// Kernel code that may fault on purpose (e.g. reading a pointer it does not
+// trust) records the address of the faulting instruction and of a recovery
+// label in the `__ex_table` section. When a #GP or #PF hits a recorded
+// instruction the handler resumes at the recovery label instead of dying.
+//
+// Entries are emitted by inline assembly:
+//
+//     2: <instruction that may fault>
+//     3: <recovery>
+//     .pushsection __ex_table, "a"
+//     .balign 8
+//     .quad 2b, 3b
+//     .popsection
+
+use super::InterruptFrame;
+
+#[repr(C)]
+struct ExceptionTableEntry {
+    instruction: u64,
+    fixup: u64,
+}
+
+unsafe extern "C" {
+    // Defined by kernel.ld around the `__ex_table` section
+    static __ex_table_start: ExceptionTableEntry;
+    static __ex_table_end: ExceptionTableEntry;
+}
+
+fn table() -> &'static [ExceptionTableEntry] {
+    unsafe {
+        let start = &raw const __ex_table_start;
+        let end = &raw const __ex_table_end;
+        let len = end.offset_from(start) as usize;
+        core::slice::from_raw_parts(start, len)
+    }
+}
+
+/// Returns the recovery address registered for the instruction at `rip`.
+pub fn search(rip: u64) -> Option<u64> {
+    table().iter().find(|entry| entry.instruction == rip).map(|entry| entry.fixup)
+}
+
+/// Redirects the interrupted code to its recovery address, if it has one.
+/// Returns true if the exception was fixed up.
+pub(super) fn fixup_exception(frame: &mut InterruptFrame) -> bool {
+    match search(frame.rip) {
+        Some(fixup) => {
+            frame.rip = fixup;
+            true
+        }
+        None => false,
+    }
+}
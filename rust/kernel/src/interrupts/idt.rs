@@ -0,0 +1,290 @@
+use core::arch::asm;
+use core::fmt;
+
+pub const IDT_ENTRIES: usize = 256;
+
+/// Selector of a GDT segment.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[repr(transparent)]
+pub struct SegmentSelector(pub u16);
+
+impl SegmentSelector {
+    /// 64-bit code segment set up by boot.asm
+    pub const KERNEL_CODE: SegmentSelector = SegmentSelector(0x08);
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[repr(u8)]
+pub enum GateType {
+    /// Clears IF on entry, used for IRQs and most exceptions
+    Interrupt = 0xE,
+    /// Leaves IF untouched
+    Trap = 0xF,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[repr(u8)]
+pub enum PrivilegeLevel {
+    Ring0 = 0,
+    Ring1 = 1,
+    Ring2 = 2,
+    Ring3 = 3,
+}
+
+/// Assembly entry stub of a vector. The CPU pushes an error code for some
+/// exceptions only; every other stub pushes a dummy one so that all of them
+/// build the same `InterruptFrame`. Installing a stub of the wrong kind would
+/// misalign the frame, `Idt::set_handler` refuses to.
+pub trait EntryStub: Copy {
+    const HAS_ERROR_CODE: bool;
+
+    fn address(self) -> u64;
+}
+
+/// Stub of an exception the CPU pushes an error code for.
+#[derive(Clone, Copy)]
+pub struct ErrorCodeStub(pub unsafe extern "C" fn());
+
+/// Stub pushing a dummy error code.
+#[derive(Clone, Copy)]
+pub struct NoErrorCodeStub(pub unsafe extern "C" fn());
+
+impl EntryStub for ErrorCodeStub {
+    const HAS_ERROR_CODE: bool = true;
+
+    fn address(self) -> u64 {
+        self.0 as *const () as u64
+    }
+}
+
+impl EntryStub for NoErrorCodeStub {
+    const HAS_ERROR_CODE: bool = false;
+
+    fn address(self) -> u64 {
+        self.0 as *const () as u64
+    }
+}
+
+/// True for the exceptions the CPU pushes an error code for.
+pub const fn cpu_pushes_error_code(vector: u8) -> bool {
+    matches!(vector, 8 | 10..=14 | 17 | 21 | 29 | 30)
+}
+
+/// Attributes of an IDT gate. `GateOptions::new()` is a present kernel
+/// interrupt gate on the kernel code segment without an IST stack.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct GateOptions {
+    pub gate_type: GateType,
+    pub privilege_level: PrivilegeLevel,
+    pub present: bool,
+    /// Interrupt Stack Table slot (1-7) of the TSS, `None` keeps the current stack
+    pub stack_index: Option<u8>,
+    pub selector: SegmentSelector,
+}
+
+impl GateOptions {
+    pub const fn new() -> GateOptions {
+        GateOptions {
+            gate_type: GateType::Interrupt,
+            privilege_level: PrivilegeLevel::Ring0,
+            present: true,
+            stack_index: None,
+            selector: SegmentSelector::KERNEL_CODE,
+        }
+    }
+
+    pub const fn gate_type(mut self, gate_type: GateType) -> GateOptions {
+        self.gate_type = gate_type;
+        self
+    }
+
+    pub const fn privilege_level(mut self, privilege_level: PrivilegeLevel) -> GateOptions {
+        self.privilege_level = privilege_level;
+        self
+    }
+
+    pub const fn present(mut self, present: bool) -> GateOptions {
+        self.present = present;
+        self
+    }
+
+    /// Switches to IST stack `index` (1-7) on entry. Needs a TSS providing it.
+    pub const fn stack_index(mut self, index: u8) -> GateOptions {
+        assert!(index >= 1 && index <= 7, "IST index must be between 1 and 7");
+        self.stack_index = Some(index);
+        self
+    }
+
+    pub const fn selector(mut self, selector: SegmentSelector) -> GateOptions {
+        self.selector = selector;
+        self
+    }
+
+    // Type and attributes byte: present, DPL, 0, gate type
+    const fn flags(self) -> u8 {
+        (self.present as u8) << 7 | (self.privilege_level as u8) << 5 | self.gate_type as u8
+    }
+
+    const fn from_raw(flags: u8, ist: u8, selector: u16) -> GateOptions {
+        GateOptions {
+            gate_type: match flags & 0xF {
+                0xF => GateType::Trap,
+                _ => GateType::Interrupt,
+            },
+            privilege_level: match (flags >> 5) & 0b11 {
+                0 => PrivilegeLevel::Ring0,
+                1 => PrivilegeLevel::Ring1,
+                2 => PrivilegeLevel::Ring2,
+                _ => PrivilegeLevel::Ring3,
+            },
+            present: flags & 0x80 != 0,
+            stack_index: if ist & 0b111 == 0 { None } else { Some(ist & 0b111) },
+            selector: SegmentSelector(selector),
+        }
+    }
+}
+
+impl Default for GateOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub struct IdtEntry {
+    offset_low: u16,        // Lower 16 bits of handler address
+    selector: u16,          // Code segment selector
+    ist: u8,                // Interrupt Stack Table
+    flags: u8,              // Type and attributes
+    offset_mid: u16,        // Middle 16 bits of handler address
+    offset_high: u32,       // Upper 32 bits of handler address
+    reserved: u32,          // Must be zero
+}
+
+impl IdtEntry {
+    pub const fn new() -> Self {
+        IdtEntry {
+            offset_low: 0,
+            selector: 0,
+            ist: 0,
+            flags: 0,
+            offset_mid: 0,
+            offset_high: 0,
+            reserved: 0,
+        }
+    }
+
+    fn set(&mut self, handler: u64, options: GateOptions) {
+        self.offset_low = handler as u16;
+        self.offset_mid = (handler >> 16) as u16;
+        self.offset_high = (handler >> 32) as u32;
+        self.selector = options.selector.0;
+        self.ist = options.stack_index.unwrap_or(0);
+        self.flags = options.flags();
+        self.reserved = 0;
+    }
+
+    pub fn handler_address(&self) -> u64 {
+        self.offset_low as u64 | (self.offset_mid as u64) << 16 | (self.offset_high as u64) << 32
+    }
+
+    pub fn options(&self) -> GateOptions {
+        GateOptions::from_raw(self.flags, self.ist, self.selector)
+    }
+
+    pub fn is_present(&self) -> bool {
+        self.flags & 0x80 != 0
+    }
+}
+
+impl Default for IdtEntry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[repr(C, packed)]
+pub struct IdtPointer {
+    limit: u16,
+    base: u64
+}
+
+#[repr(C, align(16))]
+pub struct Idt {
+    entries: [IdtEntry; IDT_ENTRIES],
+}
+
+impl Idt {
+    pub const fn new() -> Idt {
+        Idt { entries: [IdtEntry::new(); IDT_ENTRIES] }
+    }
+
+    /// Installs `stub` on `vector`.
+    ///
+    /// Panics if the stub does not match the error code behaviour of the
+    /// vector: only exceptions get a CPU error code, never IRQs.
+    pub fn set_handler<S: EntryStub>(&mut self, vector: u8, stub: S, options: GateOptions) {
+        assert!(
+            S::HAS_ERROR_CODE == cpu_pushes_error_code(vector),
+            "vector {} needs a stub {} an error code",
+            vector,
+            if cpu_pushes_error_code(vector) { "with" } else { "without" },
+        );
+        self.entries[vector as usize].set(stub.address(), options);
+    }
+
+    /// Marks `vector` as not present, raising #NP if it fires.
+    pub fn clear(&mut self, vector: u8) {
+        self.entries[vector as usize] = IdtEntry::new();
+    }
+
+    pub fn entry(&self, vector: u8) -> &IdtEntry {
+        &self.entries[vector as usize]
+    }
+
+    /// Makes the CPU use this table.
+    ///
+    /// # Safety
+    /// The table must not move nor be dropped while it is loaded.
+    pub unsafe fn load(&self) {
+        let idt_ptr = IdtPointer {
+            limit: (core::mem::size_of::<[IdtEntry; IDT_ENTRIES]>() - 1) as u16,
+            base: self.entries.as_ptr() as u64,
+        };
+
+        unsafe {
+            asm!("lidt [{}]", in(reg) &idt_ptr, options(readonly, nostack, preserves_flags));
+        }
+    }
+
+    /// Prints every present gate.
+    pub fn dump(&self, w: &mut impl fmt::Write) -> fmt::Result {
+        writeln!(w, "VEC handler            sel  type      dpl ist")?;
+        for (vector, entry) in self.entries.iter().enumerate() {
+            if !entry.is_present() {
+                continue;
+            }
+
+            let options = entry.options();
+            writeln!(w, "{:>3} {:#018x} {:04x} {:<9} {:>3} {:>3}",
+                vector,
+                entry.handler_address(),
+                options.selector.0,
+                match options.gate_type {
+                    GateType::Interrupt => "interrupt",
+                    GateType::Trap => "trap",
+                },
+                options.privilege_level as u8,
+                options.stack_index.unwrap_or(0),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Idt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,139 @@
+use core::ptr::{read_volatile, write_volatile};
+
+use crate::acpi::madt::{
+    Madt, INTI_ACTIVE_LOW, INTI_LEVEL_TRIGGERED, INTI_POLARITY_MASK, INTI_TRIGGER_MASK, MAX_IO_APICS,
+};
+use crate::paging::map_mmio;
+
+// Indirect register access: select with IOREGSEL, then read or write IOWIN
+const IOREGSEL: usize = 0x00;
+const IOWIN: usize = 0x10;
+
+const REG_VERSION: u32 = 0x01;
+const REG_REDIRECTION_BASE: u32 = 0x10;
+
+const ENTRY_ACTIVE_LOW: u64 = 1 << 13;
+const ENTRY_LEVEL_TRIGGERED: u64 = 1 << 15;
+const ENTRY_MASKED: u64 = 1 << 16;
+
+const ISA_IRQS: usize = 16;
+
+#[derive(Clone, Copy)]
+struct IoApic {
+    base: usize,
+    gsi_base: u32,
+    redirections: u32,
+}
+
+impl IoApic {
+    unsafe fn read(&self, reg: u32) -> u32 {
+        unsafe {
+            write_volatile((self.base + IOREGSEL) as *mut u32, reg);
+            read_volatile((self.base + IOWIN) as *const u32)
+        }
+    }
+
+    unsafe fn write(&self, reg: u32, value: u32) {
+        unsafe {
+            write_volatile((self.base + IOREGSEL) as *mut u32, reg);
+            write_volatile((self.base + IOWIN) as *mut u32, value);
+        }
+    }
+
+    fn handles(&self, gsi: u32) -> bool {
+        gsi >= self.gsi_base && gsi < self.gsi_base + self.redirections
+    }
+
+    fn read_entry(&self, gsi: u32) -> u64 {
+        let reg = REG_REDIRECTION_BASE + (gsi - self.gsi_base) * 2;
+        unsafe { self.read(reg) as u64 | (self.read(reg + 1) as u64) << 32 }
+    }
+
+    fn write_entry(&self, gsi: u32, entry: u64) {
+        let reg = REG_REDIRECTION_BASE + (gsi - self.gsi_base) * 2;
+        unsafe {
+            // Write the high half first so the entry is never live with a stale destination
+            self.write(reg + 1, (entry >> 32) as u32);
+            self.write(reg, entry as u32);
+        }
+    }
+}
+
+/// Where an ISA IRQ is wired once interrupt source overrides are applied.
+#[derive(Clone, Copy)]
+struct IsaRoute {
+    gsi: u32,
+    flags: u16,
+}
+
+static mut IO_APICS: [Option<IoApic>; MAX_IO_APICS] = [None; MAX_IO_APICS];
+static mut ISA_ROUTES: [IsaRoute; ISA_IRQS] = [IsaRoute { gsi: 0, flags: 0 }; ISA_IRQS];
+
+/// Maps every IO-APIC listed in the MADT and masks all of their inputs.
+#[allow(static_mut_refs)]
+pub fn init(madt: &Madt) {
+    for (slot, entry) in unsafe { IO_APICS.iter_mut() }.zip(madt.io_apics()) {
+        let base = unsafe { map_mmio(entry.address as u64, 0x1000) } as usize;
+        let mut io_apic = IoApic { base, gsi_base: entry.gsi_base, redirections: 0 };
+        io_apic.redirections = ((unsafe { io_apic.read(REG_VERSION) } >> 16) & 0xFF) + 1;
+
+        for gsi in io_apic.gsi_base..io_apic.gsi_base + io_apic.redirections {
+            io_apic.write_entry(gsi, ENTRY_MASKED);
+        }
+        *slot = Some(io_apic);
+    }
+
+    // ISA IRQs are identity mapped to GSIs unless the firmware says otherwise
+    for (irq, route) in unsafe { ISA_ROUTES.iter_mut() }.enumerate() {
+        *route = match madt.isa_override(irq as u8) {
+            Some(o) => IsaRoute { gsi: o.gsi, flags: o.flags },
+            None => IsaRoute { gsi: irq as u32, flags: 0 },
+        };
+    }
+}
+
+#[allow(static_mut_refs)]
+fn io_apic_for(gsi: u32) -> Option<IoApic> {
+    unsafe { IO_APICS.iter().flatten().find(|io_apic| io_apic.handles(gsi)).copied() }
+}
+
+/// Global system interrupt an ISA IRQ is connected to.
+pub fn isa_gsi(irq: u8) -> u32 {
+    unsafe { ISA_ROUTES[irq as usize].gsi }
+}
+
+/// Routes `gsi` to `vector` on the CPU with local APIC id `destination`.
+/// `flags` are MPS INTI flags, zero selects active high, edge triggered.
+pub fn route(gsi: u32, vector: u8, destination: u32, flags: u16, masked: bool) {
+    let Some(io_apic) = io_apic_for(gsi) else {
+        return;
+    };
+
+    let mut entry = vector as u64 | (destination as u64 & 0xFF) << 56;
+    if flags & INTI_POLARITY_MASK == INTI_ACTIVE_LOW {
+        entry |= ENTRY_ACTIVE_LOW;
+    }
+    if flags & INTI_TRIGGER_MASK == INTI_LEVEL_TRIGGERED {
+        entry |= ENTRY_LEVEL_TRIGGERED;
+    }
+    if masked {
+        entry |= ENTRY_MASKED;
+    }
+    io_apic.write_entry(gsi, entry);
+}
+
+/// Routes an ISA IRQ, honouring the interrupt source overrides of the MADT.
+pub fn route_isa_irq(irq: u8, vector: u8, destination: u32, masked: bool) {
+    let isa = unsafe { ISA_ROUTES[irq as usize] };
+    route(isa.gsi, vector, destination, isa.flags, masked);
+}
+
+pub fn set_masked(gsi: u32, masked: bool) {
+    let Some(io_apic) = io_apic_for(gsi) else {
+        return;
+    };
+
+    let entry = io_apic.read_entry(gsi);
+    let entry = if masked { entry | ENTRY_MASKED } else { entry & !ENTRY_MASKED };
+    io_apic.write_entry(gsi, entry);
+}
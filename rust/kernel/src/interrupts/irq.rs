@@ -0,0 +1,163 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::{apic, ioapic, pic, without_interrupts, InterruptFrame};
+use crate::sync::IrqSpinLock;
+
+pub const IRQ_LINES: usize = 16;
+// Maximum number of handlers that can share a single line
+const MAX_SHARED_HANDLERS: usize = 4;
+
+/// Value returned by an IRQ handler to tell the dispatcher whether the
+/// interrupt was raised by its device.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum IrqReturn {
+    Handled,
+    NotHandled,
+}
+
+pub type IrqHandler = fn(frame: &InterruptFrame) -> IrqReturn;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum IrqError {
+    InvalidLine,
+    LineFull,
+    AlreadyRegistered,
+    NotRegistered,
+}
+
+#[derive(Clone, Copy)]
+struct IrqAction {
+    name: &'static str,
+    handler: IrqHandler,
+}
+
+static IRQ_ACTIONS: IrqSpinLock<[[Option<IrqAction>; MAX_SHARED_HANDLERS]; IRQ_LINES]> =
+    IrqSpinLock::new([[None; MAX_SHARED_HANDLERS]; IRQ_LINES]);
+
+// IRQs that no registered handler claimed
+static UNHANDLED_IRQS: AtomicU64 = AtomicU64::new(0);
+
+/// Attaches `handler` to `irq`. Several handlers may share one line: they are
+/// called in registration order until one of them returns `Handled`.
+/// The line is unmasked when its first handler is registered.
+pub fn register_irq_handler(irq: u8, name: &'static str, handler: IrqHandler) -> Result<(), IrqError> {
+    let line = irq as usize;
+    if line >= IRQ_LINES {
+        return Err(IrqError::InvalidLine);
+    }
+
+    let mut lines = IRQ_ACTIONS.lock();
+    let actions = &mut lines[line];
+
+    if actions.iter().flatten().any(|action| action.name == name) {
+        return Err(IrqError::AlreadyRegistered);
+    }
+
+    let slot = actions.iter_mut().find(|slot| slot.is_none()).ok_or(IrqError::LineFull)?;
+    *slot = Some(IrqAction { name, handler });
+
+    if actions[1].is_none() {
+        unmask(irq);
+    }
+    Ok(())
+}
+
+/// Detaches the handler registered on `irq` under `name`. The line is masked
+/// again once no handler is left.
+pub fn unregister_irq_handler(irq: u8, name: &'static str) -> Result<(), IrqError> {
+    let line = irq as usize;
+    if line >= IRQ_LINES {
+        return Err(IrqError::InvalidLine);
+    }
+
+    let mut lines = IRQ_ACTIONS.lock();
+    let actions = &mut lines[line];
+
+    let index = actions
+        .iter()
+        .position(|slot| matches!(slot, Some(action) if action.name == name))
+        .ok_or(IrqError::NotRegistered)?;
+
+    // Keep the registered handlers contiguous so the dispatch order is preserved
+    actions.copy_within(index + 1.., index);
+    actions[MAX_SHARED_HANDLERS - 1] = None;
+
+    if actions[0].is_none() {
+        mask(irq);
+    }
+    Ok(())
+}
+
+/// True if at least one handler is attached to `irq`.
+pub fn has_handlers(irq: u8) -> bool {
+    let line = irq as usize;
+    line < IRQ_LINES && IRQ_ACTIONS.lock()[line][0].is_some()
+}
+
+/// Calls `f` with the name of every handler attached to `irq`.
+pub fn for_each_handler_name(irq: u8, mut f: impl FnMut(&'static str)) {
+    let line = irq as usize;
+    if line < IRQ_LINES {
+        let actions = IRQ_ACTIONS.lock()[line];
+        actions.iter().flatten().for_each(|action| f(action.name));
+    }
+}
+
+pub fn unhandled_count() -> u64 {
+    UNHANDLED_IRQS.load(Ordering::Relaxed)
+}
+
+/// Runs the handlers attached to `irq` and acknowledges the interrupt.
+pub(super) fn dispatch(irq: u8, frame: &InterruptFrame) {
+    let line = irq as usize;
+
+    // Spurious PIC interrupts are not acknowledged like real ones
+    if !apic::is_enabled() && pic::handle_spurious(irq) {
+        return;
+    }
+
+    if line < IRQ_LINES {
+        // Work on a copy so handlers are free to (un)register while running
+        let actions = IRQ_ACTIONS.lock()[line];
+
+        let handled = actions
+            .iter()
+            .flatten()
+            .any(|action| (action.handler)(frame) == IrqReturn::Handled);
+        if !handled {
+            UNHANDLED_IRQS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    end_of_interrupt(irq);
+}
+
+fn end_of_interrupt(irq: u8) {
+    if apic::is_enabled() {
+        apic::eoi();
+    } else {
+        pic::eoi(irq);
+    }
+}
+
+/// Disables delivery of `irq` at the interrupt controller currently in use.
+pub fn mask(irq: u8) {
+    without_interrupts(|| {
+        if apic::is_enabled() {
+            ioapic::set_masked(ioapic::isa_gsi(irq), true);
+        } else {
+            pic::mask(irq);
+        }
+    });
+}
+
+/// Enables delivery of `irq` at the interrupt controller currently in use.
+pub fn unmask(irq: u8) {
+    without_interrupts(|| {
+        if apic::is_enabled() {
+            ioapic::set_masked(ioapic::isa_gsi(irq), false);
+        } else {
+            pic::unmask(irq);
+        }
+    });
+}
@@ -1,209 +1,199 @@
-mod keyboard;
-
-use core::arch::asm;
-use crate::{display::writer, interrupts::keyboard::{Action, KeyType}};
-use keyboard::{Keyboard, KeyState};
-
-
-#[repr(C, packed)]
-#[derive(Clone, Copy)]
-pub struct IdtEntry {
-    offset_low: u16,        // Lower 16 bits of handler address
-    selector: u16,          // Code segment selector
-    ist: u8,                // Interrupt Stack Table
-    flags: u8,              // Type and attributes
-    offset_mid: u16,        // Middle 16 bits of handler address
-    offset_high: u32,       // Upper 32 bits of handler address
-    reserved: u32,          // Must be zero
+pub mod apic;
+pub mod fixup;
+pub mod idt;
+pub mod ioapic;
+mod irq;
+pub mod pic;
+pub mod stats;
+mod vectors;
+
+use core::fmt;
+use crate::acpi::madt;
+use crate::{debugger, watchdog};
+use crate::cpu::{disable_interrupts, restore_interrupts};
+use crate::sync::IrqSpinLock;
+use apic::ApicError;
+use idt::{ErrorCodeStub, GateOptions, Idt, NoErrorCodeStub};
+
+pub use irq::{
+    mask, register_irq_handler, unmask, unregister_irq_handler, IrqError, IrqHandler, IrqReturn,
+};
+pub use vectors::{
+    allocate_vector, free_vector, register_vector_handler, vector_name, VectorError,
+    VectorHandler, FIRST_DYNAMIC_VECTOR,
+};
+
+
+const EXCEPTION_NAMES: [&str; 32] = [
+    "Divide error", "Debug", "NMI", "Breakpoint", "Overflow", "Bound range",
+    "Invalid opcode", "Device not available", "Double fault", "Coprocessor overrun",
+    "Invalid TSS", "Segment not present", "Stack fault", "General protection",
+    "Page fault", "Reserved", "x87 FPU error", "Alignment check", "Machine check",
+    "SIMD exception", "Virtualization", "Control protection", "Reserved", "Reserved",
+    "Reserved", "Reserved", "Reserved", "Reserved", "Hypervisor injection",
+    "VMM communication", "Security exception", "Reserved",
+];
+
+pub fn exception_name(vector: u8) -> &'static str {
+    EXCEPTION_NAMES.get(vector as usize).copied().unwrap_or("Unknown")
 }
 
-impl IdtEntry {
-    pub const fn new() -> Self {
-        IdtEntry {
-            offset_low: 0,
-            selector: 0,
-            ist: 0,
-            flags: 0,
-            offset_mid: 0,
-            offset_high: 0,
-            reserved: 0,
-        }
+static IDT: IrqSpinLock<Idt> = IrqSpinLock::new(Idt::new());
+
+pub fn init_idt() {
+    vectors::init();
+
+    // Every vector gets a stub: exceptions reach the debugger, anything else
+    // goes through the vector dispatch table
+    let mut idt = IDT.lock();
+    for vector in 0..=u8::MAX {
+        set_entry_stub(&mut idt, vector, GateOptions::new());
     }
 
-    pub fn set_handler(&mut self, handler: u64, selector: u16, flags: u8) {
-        self.offset_low = handler as u16;
-        self.offset_mid = (handler >> 16) as u16;
-        self.offset_high = (handler >> 32) as u32;
-        self.selector = selector;
-        self.ist = 0;
-        self.flags = flags;
-        self.reserved = 0;
+    // The table lives in a static, it never moves after being loaded
+    unsafe {
+        idt.load();
     }
 }
 
-#[repr(C, packed)]
-pub struct IdtPointer {
-    limit: u16,
-    base: u64
+/// Changes the gate of `vector`, e.g. to let user mode raise a syscall gate.
+pub fn set_gate_options(vector: u8, options: GateOptions) {
+    set_entry_stub(&mut IDT.lock(), vector, options);
 }
 
-// IDT with 256 entries
-pub static mut IDT: [IdtEntry; 256] = [IdtEntry::new(); 256];
-
-// TODO: move in periferal crate or module
-pub static mut KEYBOARD: Keyboard = Keyboard::new();
-
-pub fn init_idt() {
-    unsafe {
-        // Set up exception handlers (interrupts 0-31)
-        // Flags 0x8E: Presetn, DPL (00 = Kernel level), Storage segment, Gate type (64-bit
-        // interrupt gate)
-        IDT[0].set_handler(divide_by_zero_handler as u64, 0x08, 0x8E);
-        IDT[13].set_handler(general_protection_fault_handler as u64, 0x08, 0x8E);
-        IDT[14].set_handler(page_fault_handler as u64, 0x08, 0x8E);
-        
-        // Set up IRQ handlers (interrupts 32-47)
-        IDT[32].set_handler(timer_handler as u64, 0x08, 0x8E);
-        IDT[33].set_handler(keyboard_handler as u64, 0x08, 0x8E);
-        
-        // Load IDT
-        let idt_ptr = IdtPointer {
-            limit: (core::mem::size_of::<[IdtEntry; 256]>() - 1) as u16,
-            base: &raw const IDT as *const _ as u64,
-        };
-
-        asm!("lidt [{}]", in(reg) &idt_ptr, options(readonly, nostack, preserves_flags));
-    }
+/// Prints the gates currently installed in the IDT.
+pub fn dump_idt(w: &mut impl fmt::Write) -> fmt::Result {
+    IDT.lock().dump(w)
 }
 
-// External assembly handlers
+// Entry stubs generated by interrupts.asm, indexed by vector. Each vector has
+// a stub in exactly one of the tables, depending on the macro that built it.
 unsafe extern "C" {
-    fn divide_by_zero_handler();
-    fn general_protection_fault_handler();
-    fn page_fault_handler();
-    fn timer_handler();
-    fn keyboard_handler();
+    static error_code_stub_table: [Option<unsafe extern "C" fn()>; 256];
+    static no_error_code_stub_table: [Option<unsafe extern "C" fn()>; 256];
+}
+
+fn set_entry_stub(idt: &mut Idt, vector: u8, options: GateOptions) {
+    let index = vector as usize;
+    let stubs = unsafe { (error_code_stub_table[index], no_error_code_stub_table[index]) };
+    match stubs {
+        (Some(stub), None) => idt.set_handler(vector, ErrorCodeStub(stub), options),
+        (None, Some(stub)) => idt.set_handler(vector, NoErrorCodeStub(stub), options),
+        _ => panic!("vector {} has no single entry stub", vector),
+    }
 }
 
 #[repr(C)]
 pub struct InterruptFrame {
-    r15: u64, r14: u64, r13: u64, r12: u64,
-    r11: u64, r10: u64, r9: u64, r8: u64,
-    rbp: u64, rdi: u64, rsi: u64, rdx: u64,
-    rcx: u64, rbx: u64, rax: u64,
-    interrupt_number: u64,
-    error_code: u64,
-    rip: u64,
-    cs: u64,
-    rflags: u64,
-    rsp: u64,
-    ss: u64,
+    pub r15: u64, pub r14: u64, pub r13: u64, pub r12: u64,
+    pub r11: u64, pub r10: u64, pub r9: u64, pub r8: u64,
+    pub rbp: u64, pub rdi: u64, pub rsi: u64, pub rdx: u64,
+    pub rcx: u64, pub rbx: u64, pub rax: u64,
+    pub interrupt_number: u64,
+    pub error_code: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
 }
 
-#[unsafe(no_mangle)]
-pub extern "C" fn rust_exception_handler(frame: &InterruptFrame) {
-    // TODO: ADD VGA printing here to show errors
-    match frame.interrupt_number {
-        0 => {
-            // Divide by zero
-            // Print error message
-            loop {}  // Halt
-        }
-        13 => {
-            // General protection fault
-            // Print error and registers
-            loop {}
-        }
-        14 => {
-            // Page fault
-            // You can read CR2 register to get fault address
-            loop {}
-        }
-        _ => {
-            // Unknown exception
-            loop {}
-        }
+impl fmt::Display for InterruptFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "RIP={:016x} RSP={:016x} RFL={:016x}", self.rip, self.rsp, self.rflags)?;
+        writeln!(f, "RAX={:016x} RBX={:016x} RCX={:016x}", self.rax, self.rbx, self.rcx)?;
+        writeln!(f, "RDX={:016x} RSI={:016x} RDI={:016x}", self.rdx, self.rsi, self.rdi)?;
+        writeln!(f, "RBP={:016x} R8 ={:016x} R9 ={:016x}", self.rbp, self.r8, self.r9)?;
+        writeln!(f, "R10={:016x} R11={:016x} R12={:016x}", self.r10, self.r11, self.r12)?;
+        writeln!(f, "R13={:016x} R14={:016x} R15={:016x}", self.r13, self.r14, self.r15)?;
+        write!(f, "CS={:04x} SS={:04x} VEC={} ERR={:x}",
+            self.cs, self.ss, self.interrupt_number, self.error_code)
     }
 }
 
 #[unsafe(no_mangle)]
-pub extern "C" fn rust_irq_handler(frame: &InterruptFrame) {
-    let irq = frame.interrupt_number - 32;
-    
-    match irq {
-        0 => {
-            // Timer interrupt
-            // You might increment a tick counter here
-        }
-        1 => {
-            let writer = unsafe { writer() };
-            // Keyboard interrupt
-            // Read scancode from port 0x60
-            let scancode = unsafe { inb(0x60) };
-            #[allow(static_mut_refs)]
-            let key_info = unsafe { KEYBOARD.scan(scancode) };
-            
-            if key_info.state == KeyState::Pressed {
-                if let Some(chr) = key_info.key.print() { 
-                    writer.write_byte(chr);
-                }
-                else if let KeyType::Action(action) = key_info.key {
-                    match action {
-                        Action::Delete => {
-                            writer.delete_last_char();
-                        },
-                        _ => {}
-                    }
-                }
-            }
-            // Process keyboard input
-        }
-        _ => {}
+pub extern "C" fn rust_exception_handler(frame: &mut InterruptFrame) {
+    let vector = frame.interrupt_number as u8;
+    let start = stats::enter(vector);
+
+    if handle_exception(frame) {
+        stats::exit(vector, start);
+        return;
     }
-    
-    // Send End of Interrupt (EOI) signal to PIC
-    unsafe {
-        if irq >= 8 {
-            outb(0xA0, 0x20);  // EOI to slave PIC
-        }
-        outb(0x20, 0x20);      // EOI to master PIC
+
+    // The debugger already reported the exception and the registers, halt
+    // for good: NMIs still wake the CPU up
+    loop {
+        unsafe { core::arch::asm!("cli", "hlt", options(nomem, nostack)) };
     }
 }
 
-// Port I/O helper functions
-unsafe fn outb(port: u16, value: u8) {
-    asm!("out dx, al", in("dx") port, in("al") value, options(nomem, nostack));
+// True if execution can resume
+fn handle_exception(frame: &mut InterruptFrame) -> bool {
+    // Faults expected by the code that took them, see `fixup`
+    if matches!(frame.interrupt_number, 13 | 14) && fixup::fixup_exception(frame) {
+        return true;
+    }
+
+    // NMIs raised by the hard-lockup detector
+    if frame.interrupt_number == 2 && watchdog::handle_nmi(frame) {
+        return true;
+    }
+
+    // Breakpoints and single steps belong to the debugger, any other exception
+    // is fatal and gives the debugger a chance to inspect it first
+    debugger::handle_exception(frame)
 }
 
-unsafe fn inb(port: u16) -> u8 {
-    let value: u8;
-    asm!("in al, dx", out("al") value, in("dx") port, options(nomem, nostack));
-    value
+#[unsafe(no_mangle)]
+pub extern "C" fn rust_irq_handler(frame: &InterruptFrame) {
+    let vector = frame.interrupt_number as u8;
+    let start = stats::enter(vector);
+
+    vectors::dispatch(vector, frame);
+
+    stats::exit(vector, start);
+}
+
+/// Runs `f` with interrupts disabled, restoring the previous interrupt flag
+/// afterwards.
+pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    let enabled = disable_interrupts();
+    let ret = f();
+    restore_interrupts(enabled);
+    ret
 }
 
 pub fn init_pic() {
-    unsafe {
-        // ICW1: Initialize PIC (cascade mode)
-        outb(0x20, 0x11);  // Master PIC
-        outb(0xA0, 0x11);  // Slave PIC
-        
-        // ICW2: Remap IRQs
-        // Master PIC: IRQ 0-7 → interrupts 32-39
-        outb(0x21, 32);
-        // Slave PIC: IRQ 8-15 → interrupts 40-47
-        outb(0xA1, 40);
-        
-        // ICW3: Tell master about slave at IRQ2
-        outb(0x21, 0x04);
-        // Tell slave its cascade identity
-        outb(0xA1, 0x02);
-        
-        // ICW4: 8086 mode
-        outb(0x21, 0x01);
-        outb(0xA1, 0x01);
-        
-        // Unmask all IRQs (enable them)
-        outb(0x21, 0x00);
-        outb(0xA1, 0x00);
+    pic::init();
+}
+
+/// Switches interrupt delivery from the 8259 pair to the local APIC and the
+/// IO-APICs described by the ACPI MADT. ISA IRQs keep their vectors (32-47)
+/// and are delivered to the calling CPU.
+pub fn init_apic() -> Result<(), ApicError> {
+    if !apic::is_supported() {
+        return Err(ApicError::Unsupported);
+    }
+
+    let madt = madt::parse().map_err(ApicError::Acpi)?;
+    if madt.io_apics().is_empty() {
+        return Err(ApicError::NoIoApic);
     }
+
+    without_interrupts(|| {
+        pic::disable();
+        apic::init_local(&madt);
+        ioapic::init(&madt);
+
+        let cpu = apic::id();
+        for irq in 0..irq::IRQ_LINES as u8 {
+            // IRQ 2 is the PIC cascade, it never fires on its own
+            if irq != 2 {
+                ioapic::route_isa_irq(irq, 32 + irq, cpu, !irq::has_handlers(irq));
+            }
+        }
+    });
+
+    Ok(())
 }
@@ -0,0 +1,133 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::io::{inb, outb};
+
+const MASTER_COMMAND: u16 = 0x20;
+const MASTER_DATA: u16 = 0x21;
+const SLAVE_COMMAND: u16 = 0xA0;
+const SLAVE_DATA: u16 = 0xA1;
+
+const COMMAND_EOI: u8 = 0x20;
+// OCW3: the next read of the command port returns the IRR or the ISR
+const COMMAND_READ_IRR: u8 = 0x0A;
+const COMMAND_READ_ISR: u8 = 0x0B;
+
+const CASCADE_IRQ: u8 = 2;
+
+static SPURIOUS_IRQS: AtomicU64 = AtomicU64::new(0);
+
+pub fn init() {
+    unsafe {
+        // ICW1: Initialize PIC (cascade mode)
+        outb(MASTER_COMMAND, 0x11);  // Master PIC
+        outb(SLAVE_COMMAND, 0x11);   // Slave PIC
+        
+        // ICW2: Remap IRQs
+        // Master PIC: IRQ 0-7 → interrupts 32-39
+        outb(MASTER_DATA, 32);
+        // Slave PIC: IRQ 8-15 → interrupts 40-47
+        outb(SLAVE_DATA, 40);
+        
+        // ICW3: Tell master about slave at IRQ2
+        outb(MASTER_DATA, 0x04);
+        // Tell slave its cascade identity
+        outb(SLAVE_DATA, 0x02);
+        
+        // ICW4: 8086 mode
+        outb(MASTER_DATA, 0x01);
+        outb(SLAVE_DATA, 0x01);
+        
+        // Mask everything but the cascade, lines are unmasked once a
+        // handler is registered
+        outb(MASTER_DATA, !(1 << CASCADE_IRQ));
+        outb(SLAVE_DATA, 0xFF);
+    }
+}
+
+pub fn mask(irq: u8) {
+    let (port, bit) = data_port(irq);
+    unsafe { outb(port, inb(port) | bit) };
+}
+
+pub fn unmask(irq: u8) {
+    let (port, bit) = data_port(irq);
+    unsafe { outb(port, inb(port) & !bit) };
+}
+
+/// Returns the mask register of both chips, slave in the high byte.
+pub fn masks() -> u16 {
+    unsafe { (inb(SLAVE_DATA) as u16) << 8 | inb(MASTER_DATA) as u16 }
+}
+
+/// In-Service Register: IRQs being serviced, slave in the high byte.
+pub fn read_isr() -> u16 {
+    read_register(COMMAND_READ_ISR)
+}
+
+/// Interrupt Request Register: IRQs raised but not yet delivered, slave in the high byte.
+pub fn read_irr() -> u16 {
+    read_register(COMMAND_READ_IRR)
+}
+
+fn read_register(ocw3: u8) -> u16 {
+    unsafe {
+        outb(MASTER_COMMAND, ocw3);
+        outb(SLAVE_COMMAND, ocw3);
+        (inb(SLAVE_COMMAND) as u16) << 8 | inb(MASTER_COMMAND) as u16
+    }
+}
+
+fn data_port(irq: u8) -> (u16, u8) {
+    if irq < 8 {
+        (MASTER_DATA, 1 << irq)
+    } else {
+        (SLAVE_DATA, 1 << (irq - 8))
+    }
+}
+
+/// Checks whether IRQ 7 or 15 is a spurious interrupt, i.e. the line was
+/// deasserted before the PIC could deliver it. The PIC then reports its lowest
+/// priority line without setting the ISR bit. A spurious interrupt must not
+/// get a normal EOI: nothing is in service on that chip, but for IRQ 15 the
+/// master did see the cascade and still needs its EOI.
+///
+/// Returns true if the interrupt was spurious and has already been dealt with.
+pub fn handle_spurious(irq: u8) -> bool {
+    if irq != 7 && irq != 15 {
+        return false;
+    }
+
+    if read_isr() & (1 << irq) != 0 {
+        return false;
+    }
+
+    if irq == 15 {
+        unsafe { outb(MASTER_COMMAND, COMMAND_EOI) };
+    }
+    SPURIOUS_IRQS.fetch_add(1, Ordering::Relaxed);
+    true
+}
+
+/// Number of spurious IRQ 7/15 received so far.
+pub fn spurious_count() -> u64 {
+    SPURIOUS_IRQS.load(Ordering::Relaxed)
+}
+
+/// Masks every line, used when the IO-APIC takes over interrupt delivery.
+/// The PIC stays remapped so a stray interrupt never lands on an exception vector.
+pub fn disable() {
+    unsafe {
+        outb(MASTER_DATA, 0xFF);
+        outb(SLAVE_DATA, 0xFF);
+    }
+}
+
+pub fn eoi(irq: u8) {
+    // Send End of Interrupt (EOI) signal to PIC
+    unsafe {
+        if irq >= 8 {
+            outb(SLAVE_COMMAND, COMMAND_EOI);   // EOI to slave PIC
+        }
+        outb(MASTER_COMMAND, COMMAND_EOI);      // EOI to master PIC
+    }
+}
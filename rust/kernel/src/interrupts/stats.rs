@@ -0,0 +1,142 @@
+use core::fmt;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use super::{apic, exception_name, irq, pic, vectors};
+use crate::cpu::rdtsc;
+
+pub const MAX_CPUS: usize = 8;
+const VECTORS: usize = 256;
+
+struct VectorTiming {
+    min_cycles: AtomicU64,
+    max_cycles: AtomicU64,
+    total_cycles: AtomicU64,
+    // Handler executions that returned, exceptions that never do are only counted
+    samples: AtomicU64,
+}
+
+static COUNTS: [[AtomicU64; VECTORS]; MAX_CPUS] =
+    [const { [const { AtomicU64::new(0) }; VECTORS] }; MAX_CPUS];
+static TIMINGS: [VectorTiming; VECTORS] = [const {
+    VectorTiming {
+        min_cycles: AtomicU64::new(u64::MAX),
+        max_cycles: AtomicU64::new(0),
+        total_cycles: AtomicU64::new(0),
+        samples: AtomicU64::new(0),
+    }
+}; VECTORS];
+
+// Number of CPU columns to print, grows as CPUs take interrupts
+static CPUS_SEEN: AtomicUsize = AtomicUsize::new(1);
+
+/// Statistics of a single vector. Durations are in TSC cycles.
+#[derive(Clone, Copy, Debug)]
+pub struct VectorStats {
+    pub count: u64,
+    pub per_cpu: [u64; MAX_CPUS],
+    pub min_cycles: u64,
+    pub avg_cycles: u64,
+    pub max_cycles: u64,
+}
+
+fn cpu_index() -> usize {
+    let cpu = if apic::is_enabled() { apic::id() as usize } else { 0 };
+    cpu.min(MAX_CPUS - 1)
+}
+
+/// Counts an occurrence of `vector` and returns the timestamp to pass to `exit`.
+pub(super) fn enter(vector: u8) -> u64 {
+    let cpu = cpu_index();
+    COUNTS[cpu][vector as usize].fetch_add(1, Ordering::Relaxed);
+    CPUS_SEEN.fetch_max(cpu + 1, Ordering::Relaxed);
+    rdtsc()
+}
+
+/// Records how long the handler of `vector` ran.
+pub(super) fn exit(vector: u8, start: u64) {
+    let cycles = rdtsc().saturating_sub(start);
+    let timing = &TIMINGS[vector as usize];
+    timing.min_cycles.fetch_min(cycles, Ordering::Relaxed);
+    timing.max_cycles.fetch_max(cycles, Ordering::Relaxed);
+    timing.total_cycles.fetch_add(cycles, Ordering::Relaxed);
+    timing.samples.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn vector_stats(vector: u8) -> VectorStats {
+    let mut per_cpu = [0; MAX_CPUS];
+    for (cpu, count) in per_cpu.iter_mut().enumerate() {
+        *count = COUNTS[cpu][vector as usize].load(Ordering::Relaxed);
+    }
+
+    let timing = &TIMINGS[vector as usize];
+    let samples = timing.samples.load(Ordering::Relaxed);
+    VectorStats {
+        count: per_cpu.iter().sum(),
+        per_cpu,
+        min_cycles: if samples == 0 { 0 } else { timing.min_cycles.load(Ordering::Relaxed) },
+        avg_cycles: timing.total_cycles.load(Ordering::Relaxed).checked_div(samples).unwrap_or(0),
+        max_cycles: timing.max_cycles.load(Ordering::Relaxed),
+    }
+}
+
+/// Resets every counter and timing.
+pub fn reset() {
+    for cpu in COUNTS.iter() {
+        for count in cpu.iter() {
+            count.store(0, Ordering::Relaxed);
+        }
+    }
+    for timing in TIMINGS.iter() {
+        timing.min_cycles.store(u64::MAX, Ordering::Relaxed);
+        timing.max_cycles.store(0, Ordering::Relaxed);
+        timing.total_cycles.store(0, Ordering::Relaxed);
+        timing.samples.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Prints a table of every vector that fired, in the spirit of /proc/interrupts.
+pub fn dump(w: &mut impl fmt::Write) -> fmt::Result {
+    let cpus = CPUS_SEEN.load(Ordering::Relaxed);
+
+    write!(w, "VEC ")?;
+    for cpu in 0..cpus {
+        write!(w, "    CPU{}", cpu)?;
+    }
+    writeln!(w, " {:>7} {:>7} {:>8}  name", "min", "avg", "max")?;
+
+    for vector in 0..VECTORS {
+        let stats = vector_stats(vector as u8);
+        if stats.count == 0 {
+            continue;
+        }
+
+        write!(w, "{:>3}:", vector)?;
+        for count in &stats.per_cpu[..cpus] {
+            write!(w, " {:>7}", count)?;
+        }
+        write!(w, " {:>7} {:>7} {:>8}  ", stats.min_cycles, stats.avg_cycles, stats.max_cycles)?;
+        write_vector_name(w, vector as u8)?;
+        writeln!(w)?;
+    }
+
+    writeln!(w, "SPU: {:>7}  PIC spurious", pic::spurious_count())?;
+    writeln!(w, "ERR: {:>7}  unhandled IRQs", irq::unhandled_count())?;
+    writeln!(w, "UNV: {:>7}  unregistered vectors", vectors::unhandled_count())
+}
+
+fn write_vector_name(w: &mut impl fmt::Write, vector: u8) -> fmt::Result {
+    match vector {
+        0..=31 => write!(w, "{}", exception_name(vector)),
+        32..=47 => {
+            let line = vector - 32;
+            write!(w, "IRQ{}", line)?;
+            let mut separator = " ";
+            irq::for_each_handler_name(line, |name| {
+                let _ = write!(w, "{}{}", separator, name);
+                separator = ", ";
+            });
+            Ok(())
+        }
+        _ => write!(w, "{}", vectors::vector_name(vector).unwrap_or("-")),
+    }
+}
@@ -0,0 +1,138 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::{apic, irq, InterruptFrame};
+use crate::sync::IrqSpinLock;
+
+const VECTORS: usize = 256;
+
+/// First vector after the ISA IRQs, vectors from here up are handed out at runtime.
+pub const FIRST_DYNAMIC_VECTOR: u8 = 48;
+
+/// Handler of a vector. The dispatcher acknowledges the local APIC after it
+/// returns, except for the vectors reserved for the ISA IRQs and the APIC
+/// spurious interrupt which take care of it themselves.
+pub type VectorHandler = fn(vector: u8, frame: &InterruptFrame);
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum VectorError {
+    /// Exceptions, ISA IRQs and the APIC spurious vector are not up for grabs
+    Reserved,
+    InUse,
+    NoFreeVector,
+    NotRegistered,
+}
+
+#[derive(Clone, Copy)]
+struct VectorAction {
+    name: &'static str,
+    handler: VectorHandler,
+    // Send the local APIC EOI once the handler returns
+    eoi: bool,
+}
+
+static VECTOR_ACTIONS: IrqSpinLock<[Option<VectorAction>; VECTORS]> =
+    IrqSpinLock::new([None; VECTORS]);
+
+// Interrupts on a vector nobody registered
+static UNHANDLED_VECTORS: AtomicU64 = AtomicU64::new(0);
+
+/// Installs the handlers of the fixed vectors.
+pub(super) fn init() {
+    let mut actions = VECTOR_ACTIONS.lock();
+
+    for line in 0..irq::IRQ_LINES as u8 {
+        actions[32 + line as usize] =
+            Some(VectorAction { name: "ISA", handler: isa_irq, eoi: false });
+    }
+
+    actions[apic::SPURIOUS_VECTOR as usize] =
+        Some(VectorAction { name: "APIC spurious", handler: spurious, eoi: false });
+}
+
+fn isa_irq(vector: u8, frame: &InterruptFrame) {
+    irq::dispatch(vector - 32, frame);
+}
+
+// Spurious APIC interrupts are not in service, an EOI would retire another one
+fn spurious(_vector: u8, _frame: &InterruptFrame) {}
+
+fn is_reserved(vector: u8) -> bool {
+    vector < FIRST_DYNAMIC_VECTOR || vector == apic::SPURIOUS_VECTOR
+}
+
+/// Attaches `handler` to a specific vector, for the ones whose number is
+/// fixed by convention such as the syscall gate.
+pub fn register_vector_handler(
+    vector: u8,
+    name: &'static str,
+    handler: VectorHandler,
+) -> Result<(), VectorError> {
+    if is_reserved(vector) {
+        return Err(VectorError::Reserved);
+    }
+
+    let mut actions = VECTOR_ACTIONS.lock();
+    let slot = &mut actions[vector as usize];
+    if slot.is_some() {
+        return Err(VectorError::InUse);
+    }
+
+    *slot = Some(VectorAction { name, handler, eoi: true });
+    Ok(())
+}
+
+/// Attaches `handler` to the lowest free vector and returns it. Higher vectors
+/// have a higher priority at the local APIC, so this hands out the lowest one.
+pub fn allocate_vector(name: &'static str, handler: VectorHandler) -> Result<u8, VectorError> {
+    let mut actions = VECTOR_ACTIONS.lock();
+
+    let vector = (FIRST_DYNAMIC_VECTOR..=u8::MAX)
+        .find(|&vector| !is_reserved(vector) && actions[vector as usize].is_none())
+        .ok_or(VectorError::NoFreeVector)?;
+
+    actions[vector as usize] = Some(VectorAction { name, handler, eoi: true });
+    Ok(vector)
+}
+
+/// Detaches the handler of `vector`, making it available again.
+pub fn free_vector(vector: u8) -> Result<(), VectorError> {
+    if is_reserved(vector) {
+        return Err(VectorError::Reserved);
+    }
+
+    VECTOR_ACTIONS.lock()[vector as usize]
+        .take()
+        .map(|_| ())
+        .ok_or(VectorError::NotRegistered)
+}
+
+/// Name `vector` was registered under.
+pub fn vector_name(vector: u8) -> Option<&'static str> {
+    VECTOR_ACTIONS.lock()[vector as usize].map(|action| action.name)
+}
+
+pub fn unhandled_count() -> u64 {
+    UNHANDLED_VECTORS.load(Ordering::Relaxed)
+}
+
+/// Runs the handler attached to `vector`.
+pub(super) fn dispatch(vector: u8, frame: &InterruptFrame) {
+    // Copy the action out so the handler is free to (un)register vectors
+    let action = VECTOR_ACTIONS.lock()[vector as usize];
+
+    match action {
+        Some(action) => {
+            (action.handler)(vector, frame);
+            if action.eoi && apic::is_enabled() {
+                apic::eoi();
+            }
+        }
+        None => {
+            UNHANDLED_VECTORS.fetch_add(1, Ordering::Relaxed);
+            // Only the local APIC delivers these, don't leave it waiting
+            if apic::is_enabled() {
+                apic::eoi();
+            }
+        }
+    }
+}
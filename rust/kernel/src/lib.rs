@@ -1,13 +1,29 @@
 #![no_std]
 #![no_main]
 
-mod interrupts;
+// Declared first so the print macros are visible everywhere
 mod display;
 
+pub mod acpi;
+mod backtrace;
+pub mod debugger;
+pub mod interrupts;
+pub mod drivers;
+mod cpu;
+mod io;
+pub mod multiboot;
+mod paging;
+mod ring;
+pub mod sync;
+pub mod time;
+pub mod uaccess;
+pub mod watchdog;
+pub mod workqueue;
+
 use core::fmt::Write;
 use core::arch::asm;
 
-use crate::display::{init_writer, writer};
+use crate::display::{init_writer, force_writer};
 //mod vga_buffer;
 
 //use vga_buffer::{Color, Writer};
@@ -15,8 +31,9 @@ use crate::display::{init_writer, writer};
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
-    let writer = unsafe { writer() };
-    let _ = writeln!(writer, "\nPANIC: {}", info);
+    if let Some(mut writer) = force_writer() {
+        let _ = writeln!(writer, "\nPANIC: {}", info);
+    }
     halt()
 }
 
@@ -30,37 +47,139 @@ fn halt() -> ! {
 }
 
 #[unsafe(no_mangle)]
-pub extern "C" fn start64() -> ! {
+pub extern "C" fn start64(multiboot_magic: u32, multiboot_info: u32) -> ! {
     // Init Writer Vga
-    unsafe {
-        init_writer();
-    }
+    init_writer();
 
-    let writer = unsafe { writer() };
-    writer.write("[x] Vga Buffer initialized");
-    writer.new_line();
+    println!("[x] Vga Buffer initialized");
+
+    if multiboot::init(multiboot_magic, multiboot_info).is_err() {
+        println!("[ ] Not booted by Multiboot, using default options");
+    }
 
     // Initialize interrupts
     interrupts::init_pic();
     interrupts::init_idt();
 
+    // Prefer the APIC when the firmware describes one, the PIC is the fallback
+    if acpi::init().is_ok() && interrupts::init_apic().is_ok() {
+        println!("[x] APIC enabled");
+    } else {
+        println!("[ ] APIC not available, using 8259 PIC");
+    }
+
+    // Attach device drivers to their IRQ lines
+    match drivers::ps2::init() {
+        Ok(ports) => {
+            if ports.second {
+                println!("[x] PS/2 controller, two ports");
+            } else {
+                println!("[x] PS/2 controller, one port");
+            }
+            if let Err(e) = drivers::keyboard::init() {
+                println!("[ ] Keyboard setup failed: {:?}", e);
+            }
+            match drivers::mouse::init() {
+                Ok(kind) => println!("[x] PS/2 mouse ({:?})", kind),
+                Err(e) => println!("[ ] PS/2 mouse not available: {:?}", e),
+            }
+        }
+        Err(e) => {
+            // Keep the keyboard as the firmware set it up, without commands
+            println!("[ ] PS/2 controller setup failed: {:?}, no mouse", e);
+            drivers::keyboard::attach();
+        }
+    }
+
+    // Keyboard layout, `keymap=` on the command line picks one (e.g. keymap=it)
+    if let Some(name) = multiboot::option("keymap") {
+        match drivers::keyboard::set_keymap(name) {
+            Ok(()) => println!("[x] Keyboard layout {}", name),
+            Err(_) => println!("[ ] Unknown keyboard layout {}, using us", name),
+        }
+    }
+
+    // Periodic tick, `pit_hz=` on the command line overrides the frequency
+    let hz = multiboot::option_u64("pit_hz")
+        .and_then(|hz| u32::try_from(hz).ok())
+        .unwrap_or(time::pit::DEFAULT_HZ);
+    if time::init(hz) == Err(time::pit::PitError::InvalidFrequency) {
+        println!("[ ] Invalid pit_hz={}, using {} Hz", hz, time::pit::DEFAULT_HZ);
+        let _ = time::init(time::pit::DEFAULT_HZ);
+    }
+    println!("[x] PIT tick at {} Hz", time::pit::frequency());
+
+    match time::hpet::init() {
+        Ok(()) => println!("[x] HPET at {} MHz", time::hpet::frequency() / 1_000_000),
+        Err(e) => println!("[ ] HPET not available: {:?}", e),
+    }
+
+    match time::tsc::calibrate() {
+        Ok(hz) => println!("[x] TSC at {} MHz", hz / 1_000_000),
+        Err(e) => println!("[ ] TSC not usable as a clock: {:?}", e),
+    }
+    println!("[x] Clock source: {:?}", time::init_clock());
+
+    // Periodic RTC interrupt, `rtc_hz=` on the command line overrides the
+    // rate, 0 turns it off
+    let mut rtc_hz = multiboot::option_u64("rtc_hz")
+        .and_then(|hz| u32::try_from(hz).ok())
+        .unwrap_or(time::rtc::DEFAULT_PERIODIC_HZ);
+    if !time::rtc::is_valid_rate(rtc_hz) {
+        println!("[ ] Invalid rtc_hz={}, using {} Hz", rtc_hz, time::rtc::DEFAULT_PERIODIC_HZ);
+        rtc_hz = time::rtc::DEFAULT_PERIODIC_HZ;
+    }
+    match time::rtc::init(rtc_hz) {
+        Ok(now) => println!("[x] RTC time {} UTC, periodic interrupt at {} Hz", now, rtc_hz),
+        Err(e) => println!("[ ] RTC setup failed: {:?}", e),
+    }
+
+    // Stop the tick while idle unless `nohz=off` is given
+    if multiboot::option("nohz") == Some("off") {
+        println!("[ ] Tickless idle disabled");
+    } else {
+        match time::idle::init() {
+            Ok(()) => println!("[x] Tickless idle on the APIC timer"),
+            Err(e) => println!("[ ] Tickless idle not available: {:?}", e),
+        }
+    }
+
+    watchdog::init(watchdog::DEFAULT_THRESHOLD_SECS);
+    println!("[x] Soft-lockup watchdog armed");
+    if watchdog::enable_hard_lockup_detector() {
+        println!("[x] Hard-lockup watchdog armed");
+    }
+
     // Enable interrupts
     unsafe {
         core::arch::asm!("sti"); // Set interrupt flag
     }
 
-    writer.write("[x] Interrupts ready");
-    writer.new_line();
+    println!("[x] Interrupts ready");
 
-    writer.write("----- System ready to be used ------");
-    writer.new_line();
-    writer.new_line();
+    println!("----- System ready to be used ------");
+    println!();
 
     
     //panic!("test");
     loop {
+        // Scheduling point: tell the watchdog we are not stuck
+        watchdog::touch();
+
+        // Bottom halves run here, with interrupts enabled
+        workqueue::run_pending();
+
+        // Sleep until the next interrupt, unless one queued more work in the
+        // meantime. The tick is stopped while sleeping when possible.
         unsafe {
-            asm!("hlt", options(nomem, nostack, preserves_flags));
+            asm!("cli", options(nostack));
+        }
+        if workqueue::has_pending() {
+            unsafe {
+                asm!("sti", options(nostack));
+            }
+        } else {
+            time::idle::idle();
         }
     }
 }
@@ -0,0 +1,84 @@
+// Information handed over by a Multiboot 1 bootloader (GRUB).
+
+use crate::sync::OnceCell;
+
+/// Value of EAX when a Multiboot bootloader jumps to the kernel.
+pub const BOOTLOADER_MAGIC: u32 = 0x2BAD_B002;
+
+// Flags telling which fields of the info structure are valid
+const INFO_CMDLINE: u32 = 1 << 2;
+
+// Maximum length of the kernel command line that is looked at
+const MAX_CMDLINE: usize = 256;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum MultibootError {
+    BadMagic,
+    NoInfo,
+}
+
+#[repr(C)]
+struct Info {
+    flags: u32,
+    mem_lower: u32,
+    mem_upper: u32,
+    boot_device: u32,
+    cmdline: u32,
+    // The remaining fields are not used yet
+}
+
+static CMDLINE: OnceCell<&'static str> = OnceCell::new();
+
+/// Reads what the bootloader passed in. `info` is the physical address of the
+/// info structure, which lies in the identity mapped first GiB.
+pub fn init(magic: u32, info: u32) -> Result<(), MultibootError> {
+    if magic != BOOTLOADER_MAGIC {
+        return Err(MultibootError::BadMagic);
+    }
+    if info == 0 {
+        return Err(MultibootError::NoInfo);
+    }
+
+    let info = unsafe { &*(info as usize as *const Info) };
+    if info.flags & INFO_CMDLINE != 0 && info.cmdline != 0 {
+        let _ = CMDLINE.set(unsafe { c_str(info.cmdline as usize as *const u8) });
+    }
+    Ok(())
+}
+
+// Borrows a NUL terminated string, cut at the first invalid UTF-8 byte
+unsafe fn c_str(ptr: *const u8) -> &'static str {
+    let mut len = 0;
+    while len < MAX_CMDLINE && unsafe { *ptr.add(len) } != 0 {
+        len += 1;
+    }
+
+    let bytes = unsafe { core::slice::from_raw_parts(ptr, len) };
+    match core::str::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(e) => unsafe { core::str::from_utf8_unchecked(&bytes[..e.valid_up_to()]) },
+    }
+}
+
+/// Kernel command line, the first word is the kernel image path.
+pub fn cmdline() -> &'static str {
+    CMDLINE.get().copied().unwrap_or("")
+}
+
+/// Value of a `key=value` option of the command line. A bare `key` gives an
+/// empty value. The last occurrence wins.
+pub fn option(key: &str) -> Option<&'static str> {
+    cmdline()
+        .split_ascii_whitespace()
+        .skip(1)
+        .filter_map(|word| match word.split_once('=') {
+            Some((name, value)) => (name == key).then_some(value),
+            None => (word == key).then_some(""),
+        })
+        .last()
+}
+
+/// Parses the value of a numeric option.
+pub fn option_u64(key: &str) -> Option<u64> {
+    option(key).and_then(|value| value.parse().ok())
+}
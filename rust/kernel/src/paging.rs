@@ -0,0 +1,83 @@
+use crate::cpu::{invlpg, read_cr3};
+
+const ENTRY_PRESENT: u64 = 1 << 0;
+const ENTRY_WRITABLE: u64 = 1 << 1;
+const ENTRY_WRITE_THROUGH: u64 = 1 << 3;
+const ENTRY_CACHE_DISABLE: u64 = 1 << 4;
+const ENTRY_HUGE: u64 = 1 << 7;
+const ENTRY_ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+const HUGE_PAGE_SIZE: u64 = 2 * 1024 * 1024;
+
+// boot.asm identity maps the first GiB, extra page directories are taken from
+// here when a mapping is requested above it (enough to cover the first 4 GiB)
+const SPARE_DIRECTORIES: usize = 3;
+
+#[repr(C, align(4096))]
+struct PageTable([u64; 512]);
+
+static mut DIRECTORIES: [PageTable; SPARE_DIRECTORIES] =
+    [const { PageTable([0; 512]) }; SPARE_DIRECTORIES];
+static mut DIRECTORIES_USED: usize = 0;
+
+/// Identity maps `[phys, phys + size)` with caching disabled, so device
+/// registers can be accessed through the returned pointer. Pages that are
+/// already mapped get caching disabled as well.
+///
+/// Only addresses below 512 GiB are supported, the mapping uses 2 MiB pages.
+pub unsafe fn map_mmio(phys: u64, size: u64) -> *mut u8 {
+    let flags = ENTRY_PRESENT | ENTRY_WRITABLE | ENTRY_WRITE_THROUGH | ENTRY_CACHE_DISABLE;
+    unsafe { identity_map(phys, size, flags) };
+    phys as *mut u8
+}
+
+/// Identity maps `[phys, phys + size)` as ordinary cached memory. Addresses that
+/// are already mapped keep their current attributes.
+pub unsafe fn map_memory(phys: u64, size: u64) -> *mut u8 {
+    unsafe { identity_map(phys, size, ENTRY_PRESENT | ENTRY_WRITABLE) };
+    phys as *mut u8
+}
+
+#[allow(static_mut_refs)]
+unsafe fn identity_map(phys: u64, size: u64, flags: u64) {
+    let start = phys & !(HUGE_PAGE_SIZE - 1);
+    let end = phys + size.max(1);
+
+    let p4 = (read_cr3() & ENTRY_ADDRESS_MASK) as *mut u64;
+
+    let mut addr = start;
+    while addr < end {
+        let p4_index = ((addr >> 39) & 511) as usize;
+        let p3_index = ((addr >> 30) & 511) as usize;
+        let p2_index = ((addr >> 21) & 511) as usize;
+
+        unsafe {
+            let p4_entry = *p4.add(p4_index);
+            assert!(p4_entry & ENTRY_PRESENT != 0, "mapping above 512 GiB is not supported");
+
+            let p3 = (p4_entry & ENTRY_ADDRESS_MASK) as *mut u64;
+            let p3_entry = p3.add(p3_index);
+            if *p3_entry & ENTRY_PRESENT == 0 {
+                assert!(DIRECTORIES_USED < SPARE_DIRECTORIES, "out of page directories");
+                let directory = &raw mut DIRECTORIES[DIRECTORIES_USED];
+                DIRECTORIES_USED += 1;
+                *p3_entry = directory as u64 | ENTRY_PRESENT | ENTRY_WRITABLE;
+            }
+
+            let p2 = (*p3_entry & ENTRY_ADDRESS_MASK) as *mut u64;
+            let p2_entry = p2.add(p2_index);
+            if *p2_entry & ENTRY_PRESENT == 0 {
+                *p2_entry = addr | flags | ENTRY_HUGE;
+                invlpg(addr);
+            } else {
+                let missing = flags & (ENTRY_WRITE_THROUGH | ENTRY_CACHE_DISABLE) & !*p2_entry;
+                if missing != 0 {
+                    *p2_entry |= missing;
+                    invlpg(addr);
+                }
+            }
+        }
+
+        addr += HUGE_PAGE_SIZE;
+    }
+}
@@ -0,0 +1,5 @@
+mod once;
+mod spinlock;
+
+pub use once::OnceCell;
+pub use spinlock::{IrqSpinLock, IrqSpinLockGuard};
@@ -0,0 +1,302 @@
+// High Precision Event Timer.
+//
+// The main counter runs at a fixed rate of at least 10 MHz and serves as a
+// clock and as the calibration reference of the TSC. The comparators raise
+// interrupts through the IO-APIC, or in legacy replacement mode take over the
+// PIT (IRQ 0) and RTC (IRQ 8) lines, which is the only option with the PIC.
+
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+use super::timer::TimerMode;
+use crate::acpi::{hpet as acpi_hpet, AcpiError};
+use crate::cpu::rdtsc;
+use crate::interrupts::{allocate_vector, apic, free_vector, ioapic, InterruptFrame, VectorError};
+use crate::paging::map_mmio;
+use crate::sync::IrqSpinLock;
+
+const MAX_TIMERS: usize = 32;
+const REGISTERS_SIZE: u64 = 0x400;
+
+const REG_CAPABILITIES: usize = 0x000;
+const REG_CONFIG: usize = 0x010;
+const REG_MAIN_COUNTER: usize = 0x0F0;
+
+const fn reg_timer_config(timer: usize) -> usize {
+    0x100 + 0x20 * timer
+}
+
+const fn reg_timer_comparator(timer: usize) -> usize {
+    0x108 + 0x20 * timer
+}
+
+const CAP_COUNTER_64BIT: u64 = 1 << 13;
+const CAP_LEGACY_ROUTE: u64 = 1 << 15;
+const CAP_NUM_TIMERS_SHIFT: u64 = 8;
+const CAP_NUM_TIMERS_MASK: u64 = 0x1F;
+const CAP_PERIOD_SHIFT: u64 = 32;
+
+const CONFIG_ENABLE: u64 = 1 << 0;
+const CONFIG_LEGACY_ROUTE: u64 = 1 << 1;
+
+const TIMER_LEVEL_TRIGGERED: u64 = 1 << 1;
+const TIMER_INT_ENABLE: u64 = 1 << 2;
+const TIMER_PERIODIC: u64 = 1 << 3;
+const TIMER_PERIODIC_CAP: u64 = 1 << 4;
+const TIMER_VALUE_SET: u64 = 1 << 6;
+const TIMER_ROUTE_SHIFT: u64 = 9;
+const TIMER_ROUTE_MASK: u64 = 0x1F << TIMER_ROUTE_SHIFT;
+const TIMER_FSB_ENABLE: u64 = 1 << 14;
+const TIMER_ROUTE_CAP_SHIFT: u64 = 32;
+
+// The period can't be larger than 100 ns (10 MHz)
+const MAX_PERIOD_FS: u64 = 100_000_000;
+const FS_PER_NS: u64 = 1_000_000;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum HpetError {
+    Acpi(AcpiError),
+    NotInitialized,
+    /// The capabilities register is not sane
+    InvalidHardware,
+    InvalidTimer,
+    /// The comparator doesn't support periodic mode, or legacy replacement
+    /// is not available
+    Unsupported,
+    /// No IO-APIC input is available to the comparator
+    NoRoute,
+    Vector(VectorError),
+}
+
+static BASE: AtomicUsize = AtomicUsize::new(0);
+static PERIOD_FS: AtomicU64 = AtomicU64::new(0);
+static TIMERS: AtomicUsize = AtomicUsize::new(0);
+static COUNTER_64BIT: AtomicBool = AtomicBool::new(false);
+// Counter value when the HPET became the clock
+static CLOCK_BASE: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone, Copy)]
+struct Comparator {
+    vector: u8,
+    callback: fn(),
+}
+
+static COMPARATORS: IrqSpinLock<[Option<Comparator>; MAX_TIMERS]> =
+    IrqSpinLock::new([None; MAX_TIMERS]);
+
+fn read(register: usize) -> u64 {
+    unsafe { read_volatile((BASE.load(Ordering::Relaxed) + register) as *const u64) }
+}
+
+fn write(register: usize, value: u64) {
+    unsafe { write_volatile((BASE.load(Ordering::Relaxed) + register) as *mut u64, value) }
+}
+
+/// Finds the HPET through ACPI, maps it and starts the main counter.
+pub fn init() -> Result<(), HpetError> {
+    let table = acpi_hpet::parse().map_err(HpetError::Acpi)?;
+    let base = unsafe { map_mmio(table.address, REGISTERS_SIZE) } as usize;
+    BASE.store(base, Ordering::Relaxed);
+
+    let capabilities = read(REG_CAPABILITIES);
+    let period = capabilities >> CAP_PERIOD_SHIFT;
+    if period == 0 || period > MAX_PERIOD_FS {
+        BASE.store(0, Ordering::Relaxed);
+        return Err(HpetError::InvalidHardware);
+    }
+
+    let timers = ((capabilities >> CAP_NUM_TIMERS_SHIFT) & CAP_NUM_TIMERS_MASK) as usize + 1;
+    PERIOD_FS.store(period, Ordering::Relaxed);
+    TIMERS.store(timers, Ordering::Relaxed);
+    COUNTER_64BIT.store(capabilities & CAP_COUNTER_64BIT != 0, Ordering::Relaxed);
+
+    // Stop the counter and the comparators before starting from a clean state
+    write(REG_CONFIG, read(REG_CONFIG) & !(CONFIG_ENABLE | CONFIG_LEGACY_ROUTE));
+    for timer in 0..timers {
+        let config = read(reg_timer_config(timer));
+        write(reg_timer_config(timer), config & !(TIMER_INT_ENABLE | TIMER_FSB_ENABLE));
+    }
+    write(REG_MAIN_COUNTER, 0);
+    write(REG_CONFIG, read(REG_CONFIG) | CONFIG_ENABLE);
+    Ok(())
+}
+
+pub fn is_available() -> bool {
+    BASE.load(Ordering::Relaxed) != 0
+}
+
+/// True if the main counter is 64 bits wide. A 32-bit one wraps after a few
+/// minutes and is only good for short measurements.
+pub fn is_64bit() -> bool {
+    COUNTER_64BIT.load(Ordering::Relaxed)
+}
+
+/// Length of a counter tick in femtoseconds.
+pub fn period_fs() -> u64 {
+    PERIOD_FS.load(Ordering::Relaxed)
+}
+
+pub fn frequency() -> u64 {
+    match period_fs() {
+        0 => 0,
+        period => 1_000_000_000_000_000 / period,
+    }
+}
+
+/// Number of comparators.
+pub fn timers() -> usize {
+    TIMERS.load(Ordering::Relaxed)
+}
+
+pub fn counter() -> u64 {
+    if is_64bit() {
+        read(REG_MAIN_COUNTER)
+    } else {
+        read(REG_MAIN_COUNTER) & 0xFFFF_FFFF
+    }
+}
+
+pub fn ticks_to_ns(ticks: u64) -> u64 {
+    (ticks as u128 * period_fs() as u128 / FS_PER_NS as u128) as u64
+}
+
+pub fn ns_to_ticks(ns: u64) -> u64 {
+    (ns as u128 * FS_PER_NS as u128 / period_fs().max(1) as u128) as u64
+}
+
+/// Busy-waits until `ticks` counter ticks elapsed, returning the TSC cycles
+/// spent meanwhile, for calibration.
+pub(super) fn measure_tsc(ticks: u64) -> u64 {
+    let start = counter();
+    let tsc_start = rdtsc();
+    while counter().wrapping_sub(start) & counter_mask() < ticks {
+        core::hint::spin_loop();
+    }
+    rdtsc() - tsc_start
+}
+
+fn counter_mask() -> u64 {
+    if is_64bit() { u64::MAX } else { 0xFFFF_FFFF }
+}
+
+/// Makes the main counter the clock, starting from now.
+pub(super) fn start_clock() {
+    CLOCK_BASE.store(counter(), Ordering::Relaxed);
+}
+
+/// Nanoseconds since `start_clock`.
+pub fn now_ns() -> u64 {
+    ticks_to_ns(counter().wrapping_sub(CLOCK_BASE.load(Ordering::Relaxed)) & counter_mask())
+}
+
+/// Fires `callback` after `ns` nanoseconds, then every `ns` in periodic
+/// mode. The comparator interrupt goes through the IO-APIC on a vector of
+/// its own, so the APIC must be enabled.
+pub fn start_timer(
+    timer: usize,
+    mode: TimerMode,
+    ns: u64,
+    callback: fn(),
+) -> Result<(), HpetError> {
+    if !is_available() {
+        return Err(HpetError::NotInitialized);
+    }
+    if timer >= timers() {
+        return Err(HpetError::InvalidTimer);
+    }
+    if !apic::is_enabled() {
+        return Err(HpetError::NoRoute);
+    }
+
+    let config = read(reg_timer_config(timer));
+    if mode == TimerMode::Periodic && config & TIMER_PERIODIC_CAP == 0 {
+        return Err(HpetError::Unsupported);
+    }
+
+    // Prefer the inputs above the ISA range, the ISA ones belong to devices
+    let routes = (config >> TIMER_ROUTE_CAP_SHIFT) as u32;
+    let candidates = match routes & !0xFFFF {
+        0 => routes,
+        above_isa => above_isa,
+    };
+    if candidates == 0 {
+        return Err(HpetError::NoRoute);
+    }
+    let gsi = candidates.trailing_zeros();
+
+    stop_timer(timer);
+    let vector = allocate_vector("hpet", hpet_interrupt).map_err(HpetError::Vector)?;
+    COMPARATORS.lock()[timer] = Some(Comparator { vector, callback });
+
+    // Edge triggered, active high
+    ioapic::route(gsi, vector, apic::id(), 0, false);
+
+    let ticks = ns_to_ticks(ns).max(1);
+    let mut config = config & !(TIMER_ROUTE_MASK | TIMER_LEVEL_TRIGGERED | TIMER_PERIODIC);
+    config |= (gsi as u64) << TIMER_ROUTE_SHIFT;
+    arm(timer, config, mode, ticks);
+    Ok(())
+}
+
+fn arm(timer: usize, config: u64, mode: TimerMode, ticks: u64) {
+    match mode {
+        TimerMode::OneShot => {
+            write(reg_timer_config(timer), config);
+            write(reg_timer_comparator(timer), counter().wrapping_add(ticks));
+        }
+        TimerMode::Periodic => {
+            // The first write sets the comparator, the second the period
+            write(reg_timer_config(timer), config | TIMER_PERIODIC | TIMER_VALUE_SET);
+            write(reg_timer_comparator(timer), counter().wrapping_add(ticks));
+            write(reg_timer_comparator(timer), ticks);
+        }
+    }
+    write(reg_timer_config(timer), read(reg_timer_config(timer)) | TIMER_INT_ENABLE);
+}
+
+/// Disables the interrupt of a comparator.
+pub fn stop_timer(timer: usize) {
+    if timer >= timers() {
+        return;
+    }
+
+    let config = read(reg_timer_config(timer));
+    write(reg_timer_config(timer), config & !TIMER_INT_ENABLE);
+
+    if let Some(comparator) = COMPARATORS.lock()[timer].take() {
+        let _ = free_vector(comparator.vector);
+    }
+}
+
+fn hpet_interrupt(vector: u8, _frame: &InterruptFrame) {
+    let comparators = *COMPARATORS.lock();
+    comparators
+        .iter()
+        .flatten()
+        .filter(|comparator| comparator.vector == vector)
+        .for_each(|comparator| (comparator.callback)());
+}
+
+/// Replaces the PIT as the source of IRQ 0 with comparator 0 running at
+/// `hz`, in legacy replacement mode. This also takes IRQ 8 away from the RTC.
+/// The tick handler attached to IRQ 0 keeps running, at the HPET rate.
+pub fn use_as_tick(hz: u32) -> Result<(), HpetError> {
+    if !is_available() {
+        return Err(HpetError::NotInitialized);
+    }
+    if read(REG_CAPABILITIES) & CAP_LEGACY_ROUTE == 0 {
+        return Err(HpetError::Unsupported);
+    }
+
+    let config = read(reg_timer_config(0));
+    if config & TIMER_PERIODIC_CAP == 0 || hz == 0 {
+        return Err(HpetError::Unsupported);
+    }
+
+    let ticks = (frequency() / hz as u64).max(1);
+    let config = config & !(TIMER_ROUTE_MASK | TIMER_LEVEL_TRIGGERED | TIMER_PERIODIC);
+    write(REG_CONFIG, read(REG_CONFIG) | CONFIG_LEGACY_ROUTE);
+    arm(0, config, TimerMode::Periodic, ticks);
+    super::set_tick_period(ticks * period_fs());
+    Ok(())
+}
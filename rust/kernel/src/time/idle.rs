@@ -0,0 +1,182 @@
+// Tickless idle.
+//
+// With nothing to do the periodic tick is only a source of wakeups. Before
+// halting, the idle loop masks IRQ 0 and arms the local APIC timer for the
+// next kernel timer instead. On wakeup the tick count catches up with the
+// monotonic clock, which therefore must not be the tick itself.
+
+use core::arch::asm;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use super::{
+    catch_up_ticks, clock_source, delay_us, monotonic_ns, ms_to_ticks, ticks, ticks_to_ns, timer,
+    tsc, ClockSource,
+};
+use crate::cpu::rdtsc;
+use crate::interrupts::{
+    allocate_vector, apic, mask, unmask, without_interrupts, InterruptFrame, VectorError,
+};
+
+const TIMER_IRQ: u8 = 0;
+
+// Longest sleep, so the tick count and the watchdogs never lag far behind
+const MAX_IDLE_MS: u64 = 1000;
+// Stopping the tick for a shorter time is not worth it
+const MIN_TICKLESS_TICKS: u64 = 2;
+// Length of the one-shot timer calibration
+const CALIBRATION_US: u64 = 10_000;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TicklessError {
+    NoApic,
+    /// The clock would stop with the tick
+    NoClock,
+    Vector(VectorError),
+}
+
+/// Idle statistics, times in nanoseconds.
+#[derive(Clone, Copy, Debug)]
+pub struct IdleStats {
+    pub entries: u64,
+    /// Entries that stopped the tick
+    pub tickless_entries: u64,
+    pub idle_ns: u64,
+    pub longest_ns: u64,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static TSC_DEADLINE: AtomicBool = AtomicBool::new(false);
+// Local APIC timer counts per millisecond, in one-shot mode
+static APIC_TIMER_PER_MS: AtomicU64 = AtomicU64::new(0);
+// Tick and clock value when the tick was stopped
+static STOPPED_AT_TICKS: AtomicU64 = AtomicU64::new(0);
+static STOPPED_AT_NS: AtomicU64 = AtomicU64::new(0);
+
+static ENTRIES: AtomicU64 = AtomicU64::new(0);
+static TICKLESS_ENTRIES: AtomicU64 = AtomicU64::new(0);
+static IDLE_NS: AtomicU64 = AtomicU64::new(0);
+static LONGEST_NS: AtomicU64 = AtomicU64::new(0);
+
+/// Allows the idle loop to stop the tick. Needs the local APIC and a clock
+/// source other than the tick.
+pub fn init() -> Result<(), TicklessError> {
+    if !apic::is_enabled() {
+        return Err(TicklessError::NoApic);
+    }
+    if clock_source() == ClockSource::Tick {
+        return Err(TicklessError::NoClock);
+    }
+
+    let vector =
+        allocate_vector("apic timer", apic_timer_interrupt).map_err(TicklessError::Vector)?;
+    let tsc_deadline = apic::tsc_deadline_supported() && tsc::is_reliable();
+    apic::init_timer(vector, tsc_deadline);
+    TSC_DEADLINE.store(tsc_deadline, Ordering::Relaxed);
+
+    if !tsc_deadline {
+        // The bus clock is unknown, count it down for a known time. The
+        // timer interrupt may fire once meanwhile, it does nothing.
+        let elapsed = without_interrupts(|| {
+            apic::set_timer_count(u32::MAX);
+            delay_us(CALIBRATION_US);
+            let elapsed = u32::MAX - apic::timer_current_count();
+            apic::set_timer_count(0);
+            elapsed
+        });
+        APIC_TIMER_PER_MS.store(elapsed as u64 * 1000 / CALIBRATION_US, Ordering::Relaxed);
+    }
+
+    ENABLED.store(true, Ordering::Release);
+    Ok(())
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+// Only there to wake the CPU up, the dispatcher sends the EOI
+fn apic_timer_interrupt(_vector: u8, _frame: &InterruptFrame) {}
+
+/// Halts until the next interrupt. Must be called with interrupts disabled,
+/// so a wakeup can't be lost between the last check for work and `hlt`.
+/// Returns with interrupts enabled.
+pub fn idle() {
+    let start = monotonic_ns();
+    let stopped = is_enabled() && stop_tick();
+
+    // `sti` only takes effect after `hlt`
+    unsafe {
+        asm!("sti", "hlt", options(nomem, nostack));
+    }
+
+    if stopped {
+        without_interrupts(restart_tick);
+    }
+
+    let slept = monotonic_ns().saturating_sub(start);
+    ENTRIES.fetch_add(1, Ordering::Relaxed);
+    if stopped {
+        TICKLESS_ENTRIES.fetch_add(1, Ordering::Relaxed);
+    }
+    IDLE_NS.fetch_add(slept, Ordering::Relaxed);
+    LONGEST_NS.fetch_max(slept, Ordering::Relaxed);
+}
+
+// Replaces the tick with a one-shot wakeup at the next timer deadline
+fn stop_tick() -> bool {
+    let now = ticks();
+    let sleep_ticks = timer::next_deadline().saturating_sub(now).min(ms_to_ticks(MAX_IDLE_MS));
+    if sleep_ticks < MIN_TICKLESS_TICKS {
+        return false;
+    }
+
+    STOPPED_AT_TICKS.store(now, Ordering::Relaxed);
+    STOPPED_AT_NS.store(monotonic_ns(), Ordering::Relaxed);
+    mask(TIMER_IRQ);
+
+    let sleep_ns = ticks_to_ns(sleep_ticks);
+    if TSC_DEADLINE.load(Ordering::Relaxed) {
+        let cycles = (sleep_ns as u128 * tsc::frequency() as u128 / 1_000_000_000) as u64;
+        apic::set_tsc_deadline(rdtsc() + cycles);
+    } else {
+        let count = sleep_ns * APIC_TIMER_PER_MS.load(Ordering::Relaxed) / 1_000_000;
+        apic::set_timer_count(count.clamp(1, u32::MAX as u64) as u32);
+    }
+    true
+}
+
+fn restart_tick() {
+    if TSC_DEADLINE.load(Ordering::Relaxed) {
+        apic::set_tsc_deadline(0);
+    } else {
+        apic::set_timer_count(0);
+    }
+
+    // Account for the ticks that were skipped
+    let slept = monotonic_ns().saturating_sub(STOPPED_AT_NS.load(Ordering::Relaxed));
+    catch_up_ticks(STOPPED_AT_TICKS.load(Ordering::Relaxed), slept);
+    unmask(TIMER_IRQ);
+}
+
+pub fn stats() -> IdleStats {
+    IdleStats {
+        entries: ENTRIES.load(Ordering::Relaxed),
+        tickless_entries: TICKLESS_ENTRIES.load(Ordering::Relaxed),
+        idle_ns: IDLE_NS.load(Ordering::Relaxed),
+        longest_ns: LONGEST_NS.load(Ordering::Relaxed),
+    }
+}
+
+/// Prints the idle statistics and the share of time spent idle.
+pub fn dump(w: &mut impl fmt::Write) -> fmt::Result {
+    let stats = stats();
+    let uptime_ns = monotonic_ns().max(1);
+    writeln!(w, "idle: {} entries, {} tickless, {} ms idle ({}%), longest {} us",
+        stats.entries,
+        stats.tickless_entries,
+        stats.idle_ns / 1_000_000,
+        stats.idle_ns as u128 * 100 / uptime_ns as u128,
+        stats.longest_ns / 1000,
+    )
+}
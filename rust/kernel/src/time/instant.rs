@@ -0,0 +1,54 @@
+use core::ops::{Add, Sub};
+use core::time::Duration;
+
+use super::monotonic_ns;
+
+/// A point on the monotonic clock, with nanosecond resolution when the TSC is
+/// the clock source.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub struct Instant(u64);
+
+impl Instant {
+    pub fn now() -> Instant {
+        Instant(monotonic_ns())
+    }
+
+    /// Nanoseconds since the clock origin, close to boot.
+    pub fn as_nanos(&self) -> u64 {
+        self.0
+    }
+
+    /// Time between `earlier` and `self`, zero if `earlier` is later.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_nanos(self.0.saturating_sub(earlier.0))
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        u64::try_from(duration.as_nanos()).ok().and_then(|ns| self.0.checked_add(ns)).map(Instant)
+    }
+
+    /// True once the clock went past `self`, for timeouts.
+    pub fn has_passed(&self) -> bool {
+        Instant::now() >= *self
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        self.checked_add(duration).expect("overflow when adding a duration to an instant")
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
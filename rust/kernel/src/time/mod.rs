@@ -0,0 +1,186 @@
+// Timekeeping: the periodic tick and the clocks derived from it.
+
+pub mod hpet;
+pub mod idle;
+mod instant;
+pub mod pit;
+pub mod rtc;
+pub mod timer;
+pub mod tsc;
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use crate::cpu::interrupts_enabled;
+use crate::interrupts::{without_interrupts, InterruptFrame};
+use crate::watchdog;
+
+pub use core::time::Duration;
+pub use instant::Instant;
+pub use pit::{delay_ms, delay_us};
+pub use rtc::{wall_clock, DateTime};
+pub use timer::{Timer, TimerError, TimerMode};
+
+// Ticks since the tick source was started
+static TICKS: AtomicU64 = AtomicU64::new(0);
+// Length of a tick in femtoseconds, PIT periods are not a whole number of ns
+static TICK_PERIOD_FS: AtomicU64 = AtomicU64::new(0);
+// Tick count and time when the period last changed, older ticks keep the
+// length they had
+static BASE_TICKS: AtomicU64 = AtomicU64::new(0);
+static BASE_NS: AtomicU64 = AtomicU64::new(0);
+
+static CLOCK_SOURCE: AtomicU8 = AtomicU8::new(ClockSource::Tick as u8);
+// Monotonic clock value when the current clock source took over
+static CLOCK_OFFSET_NS: AtomicU64 = AtomicU64::new(0);
+
+const FS_PER_MS: u128 = 1_000_000_000_000;
+const FS_PER_NS: u128 = 1_000_000;
+
+/// Where the monotonic clock comes from.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[repr(u8)]
+pub enum ClockSource {
+    Tsc,
+    /// When the TSC can't be trusted, if the HPET has a 64-bit counter
+    Hpet,
+    /// Last resort, with the resolution of a tick
+    Tick,
+}
+
+/// Starts the periodic tick on the PIT at `hz`.
+pub fn init(hz: u32) -> Result<(), pit::PitError> {
+    pit::init(hz)
+}
+
+/// Switches the monotonic clock to the best source available: the TSC if
+/// `tsc::calibrate` found it reliable, then the HPET, then the tick.
+pub fn init_clock() -> ClockSource {
+    let source = if tsc::is_reliable() {
+        ClockSource::Tsc
+    } else if hpet::is_available() && hpet::is_64bit() {
+        ClockSource::Hpet
+    } else {
+        ClockSource::Tick
+    };
+
+    // Carry on from the current value so the clock never goes back
+    without_interrupts(|| {
+        CLOCK_OFFSET_NS.store(monotonic_ns(), Ordering::Relaxed);
+        match source {
+            ClockSource::Tsc => tsc::start_clock(),
+            ClockSource::Hpet => hpet::start_clock(),
+            ClockSource::Tick => (),
+        }
+        CLOCK_SOURCE.store(source as u8, Ordering::Release);
+    });
+    source
+}
+
+pub fn clock_source() -> ClockSource {
+    match CLOCK_SOURCE.load(Ordering::Acquire) {
+        0 => ClockSource::Tsc,
+        1 => ClockSource::Hpet,
+        _ => ClockSource::Tick,
+    }
+}
+
+/// Nanoseconds on the monotonic clock, see `Instant`.
+pub fn monotonic_ns() -> u64 {
+    match clock_source() {
+        ClockSource::Tsc => CLOCK_OFFSET_NS.load(Ordering::Relaxed) + tsc::now_ns(),
+        ClockSource::Hpet => CLOCK_OFFSET_NS.load(Ordering::Relaxed) + hpet::now_ns(),
+        ClockSource::Tick => tick_time_ns(ticks()),
+    }
+}
+
+/// Ticks since boot.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Frequency of the tick, rounded to the nearest Hz.
+pub fn tick_hz() -> u64 {
+    match TICK_PERIOD_FS.load(Ordering::Relaxed) {
+        0 => 0,
+        period => (1_000_000_000_000_000 + period / 2) / period,
+    }
+}
+
+/// Milliseconds since the tick source was started.
+pub fn uptime() -> u64 {
+    tick_time_ns(ticks()) / 1_000_000
+}
+
+// Nanoseconds from the first tick to tick `ticks`, across period changes
+fn tick_time_ns(ticks: u64) -> u64 {
+    let base_ticks = BASE_TICKS.load(Ordering::Relaxed);
+    BASE_NS.load(Ordering::Relaxed) + ticks_to_ns(ticks.saturating_sub(base_ticks))
+}
+
+/// Length of `ticks` ticks at the current period, in milliseconds.
+pub fn ticks_to_ms(ticks: u64) -> u64 {
+    (ticks as u128 * TICK_PERIOD_FS.load(Ordering::Relaxed) as u128 / FS_PER_MS) as u64
+}
+
+/// Same in nanoseconds.
+pub fn ticks_to_ns(ticks: u64) -> u64 {
+    (ticks as u128 * TICK_PERIOD_FS.load(Ordering::Relaxed) as u128 / FS_PER_NS) as u64
+}
+
+/// Number of ticks covering at least `ms` milliseconds.
+pub fn ms_to_ticks(ms: u64) -> u64 {
+    match TICK_PERIOD_FS.load(Ordering::Relaxed) {
+        0 => 0,
+        period => (ms as u128 * FS_PER_MS).div_ceil(period as u128) as u64,
+    }
+}
+
+/// Sleeps for at least `ms` milliseconds, halting the CPU between ticks.
+/// Without interrupts or before the tick runs nothing would wake it up, so it
+/// busy-waits instead.
+pub fn sleep_ms(ms: u64) {
+    if !interrupts_enabled() || TICK_PERIOD_FS.load(Ordering::Relaxed) == 0 {
+        delay_ms(ms);
+        return;
+    }
+
+    // The current tick is already partly over, wait for one more
+    let deadline = ticks() + ms_to_ticks(ms) + 1;
+    while ticks() < deadline {
+        unsafe {
+            asm!("hlt", options(nomem, nostack));
+        }
+    }
+}
+
+/// Changes the length of the ticks to come. Deadlines of armed timers are in
+/// ticks, they move with it.
+fn set_tick_period(period_fs: u64) {
+    without_interrupts(|| {
+        let now = ticks();
+        BASE_NS.store(tick_time_ns(now), Ordering::Relaxed);
+        BASE_TICKS.store(now, Ordering::Relaxed);
+        TICK_PERIOD_FS.store(period_fs, Ordering::Relaxed);
+    });
+}
+
+/// Brings the tick count up to date after `elapsed_ns` without ticks, counted
+/// from tick `since`.
+fn catch_up_ticks(since: u64, elapsed_ns: u64) {
+    let period = TICK_PERIOD_FS.load(Ordering::Relaxed);
+    if period == 0 {
+        return;
+    }
+
+    let target = since + (elapsed_ns as u128 * FS_PER_NS / period as u128) as u64;
+    let now = TICKS.fetch_max(target, Ordering::Relaxed).max(target);
+    timer::tick(now);
+}
+
+/// Called by the tick source on every tick, with the interrupted frame.
+fn tick(frame: &InterruptFrame) {
+    let now = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+    timer::tick(now);
+    watchdog::tick(frame);
+}
@@ -0,0 +1,134 @@
+// Intel 8253/8254 Programmable Interval Timer.
+//
+// Channel 0 drives IRQ 0 and provides the periodic tick. Channel 2, normally
+// wired to the PC speaker, is used as a one-shot counter for busy waits.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::cpu::rdtsc;
+use crate::interrupts::{register_irq_handler, InterruptFrame, IrqError, IrqReturn};
+use crate::io::{inb, outb};
+use crate::sync::IrqSpinLock;
+
+/// Frequency of the oscillator feeding the three channels.
+pub const PIT_FREQUENCY: u32 = 1_193_182;
+/// Tick frequency used when the command line does not ask for another one.
+pub const DEFAULT_HZ: u32 = 1000;
+
+const TIMER_IRQ: u8 = 0;
+
+// One PIT period in femtoseconds (10^15 / PIT_FREQUENCY)
+const PERIOD_FS: u64 = 838_095_345;
+
+const CHANNEL0: u16 = 0x40;
+const CHANNEL2: u16 = 0x42;
+const COMMAND: u16 = 0x43;
+// Keyboard controller port B: channel 2 gate and output
+const PORT_B: u16 = 0x61;
+
+// Command byte: channel, access mode, operating mode, binary counting
+const CMD_CHANNEL0: u8 = 0b00 << 6;
+const CMD_CHANNEL2: u8 = 0b10 << 6;
+const CMD_ACCESS_LOHI: u8 = 0b11 << 4;
+const CMD_MODE_TERMINAL_COUNT: u8 = 0b000 << 1;
+const CMD_MODE_RATE_GENERATOR: u8 = 0b010 << 1;
+
+const PORT_B_GATE2: u8 = 1 << 0;
+const PORT_B_SPEAKER: u8 = 1 << 1;
+const PORT_B_OUT2: u8 = 1 << 5;
+
+// Longest busy wait done with interrupts disabled, shorter than a tick
+const MAX_DELAY_CHUNK_US: u64 = 500;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum PitError {
+    /// The divisor would not fit in 16 bits or would be below 2
+    InvalidFrequency,
+    Irq(IrqError),
+}
+
+// Reload value of channel 0, 0 means 65536
+static DIVISOR: AtomicU32 = AtomicU32::new(0);
+// Channel 2 is programmed by one waiter at a time
+static CHANNEL2_LOCK: IrqSpinLock<()> = IrqSpinLock::new(());
+
+/// Programs channel 0 to fire IRQ 0 at `hz` and attaches the tick handler.
+pub fn init(hz: u32) -> Result<(), PitError> {
+    if hz == 0 {
+        return Err(PitError::InvalidFrequency);
+    }
+    let divisor = (PIT_FREQUENCY + hz / 2) / hz;
+    if !(2..=0x10000).contains(&divisor) {
+        return Err(PitError::InvalidFrequency);
+    }
+
+    DIVISOR.store(divisor, Ordering::Relaxed);
+    super::set_tick_period(divisor as u64 * PERIOD_FS);
+
+    unsafe {
+        outb(COMMAND, CMD_CHANNEL0 | CMD_ACCESS_LOHI | CMD_MODE_RATE_GENERATOR);
+        outb(CHANNEL0, divisor as u8);
+        outb(CHANNEL0, (divisor >> 8) as u8);
+    }
+
+    register_irq_handler(TIMER_IRQ, "pit", pit_irq).map_err(PitError::Irq)
+}
+
+/// Actual frequency of channel 0 in Hz, which differs slightly from the
+/// requested one since the divisor is an integer.
+pub fn frequency() -> u32 {
+    match DIVISOR.load(Ordering::Relaxed) {
+        0 => 0,
+        divisor => PIT_FREQUENCY / divisor,
+    }
+}
+
+fn pit_irq(frame: &InterruptFrame) -> IrqReturn {
+    super::tick(frame);
+    IrqReturn::Handled
+}
+
+/// Busy-waits for `us` microseconds using channel 2. Usable before interrupts
+/// are enabled and from interrupt handlers.
+pub fn delay_us(us: u64) {
+    let mut remaining = us;
+    while remaining > 0 {
+        let chunk = remaining.min(MAX_DELAY_CHUNK_US);
+        run_channel2((chunk * PIT_FREQUENCY as u64 / 1_000_000) as u16);
+        remaining -= chunk;
+    }
+}
+
+pub fn delay_ms(ms: u64) {
+    delay_us(ms * 1000);
+}
+
+/// Counts `count` PIT periods down on channel 2 and waits for its output to
+/// rise. Returns the TSC cycles elapsed meanwhile, for calibration.
+pub(super) fn run_channel2(count: u16) -> u64 {
+    if count == 0 {
+        return 0;
+    }
+
+    let _guard = CHANNEL2_LOCK.lock();
+    unsafe {
+        // Gate and speaker off while programming
+        let port_b = inb(PORT_B) & !PORT_B_SPEAKER & !PORT_B_GATE2;
+        outb(PORT_B, port_b);
+
+        outb(COMMAND, CMD_CHANNEL2 | CMD_ACCESS_LOHI | CMD_MODE_TERMINAL_COUNT);
+        outb(CHANNEL2, count as u8);
+        outb(CHANNEL2, (count >> 8) as u8);
+
+        // The counter only runs while the gate is high
+        outb(PORT_B, port_b | PORT_B_GATE2);
+        let start = rdtsc();
+        while inb(PORT_B) & PORT_B_OUT2 == 0 {
+            core::hint::spin_loop();
+        }
+        let cycles = rdtsc() - start;
+
+        outb(PORT_B, port_b);
+        cycles
+    }
+}
@@ -0,0 +1,364 @@
+// Motorola MC146818 real-time clock, in the CMOS.
+//
+// The date is read once at boot, the wall clock then advances with the tick.
+// The periodic interrupt on IRQ 8 is available as a second tick source.
+
+use core::fmt;
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use super::uptime;
+use crate::acpi::fadt;
+use crate::interrupts::{register_irq_handler, InterruptFrame, IrqError, IrqReturn};
+use crate::io::{inb, outb};
+use crate::sync::IrqSpinLock;
+
+const RTC_IRQ: u8 = 8;
+
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0A;
+const REG_STATUS_B: u8 = 0x0B;
+const REG_STATUS_C: u8 = 0x0C;
+
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+const STATUS_A_RATE_MASK: u8 = 0x0F;
+const STATUS_B_SET: u8 = 1 << 7;
+const STATUS_B_PERIODIC: u8 = 1 << 6;
+const STATUS_B_BINARY: u8 = 1 << 2;
+const STATUS_B_24_HOUR: u8 = 1 << 1;
+const STATUS_C_PERIODIC: u8 = 1 << 6;
+const STATUS_C_ANY: u8 = 1 << 7;
+
+// Set in the hours register for PM times in 12-hour mode
+const HOUR_PM: u8 = 1 << 7;
+
+// Base frequency of the divider chain
+const RTC_BASE_HZ: u32 = 32768;
+
+pub const DEFAULT_PERIODIC_HZ: u32 = 2;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RtcError {
+    InvalidDate,
+    /// Periodic rates are powers of two between 2 and 8192 Hz
+    InvalidRate,
+    Irq(IrqError),
+}
+
+/// Calendar date and time, in UTC.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+// Serialises CMOS accesses, the index register is shared with other users
+static CMOS: IrqSpinLock<()> = IrqSpinLock::new(());
+// CMOS register of the century from the FADT, 0 if there is none
+static CENTURY_REGISTER: AtomicU8 = AtomicU8::new(0);
+// Unix time in milliseconds when `uptime()` was 0
+static BOOT_UNIX_MS: AtomicU64 = AtomicU64::new(0);
+static PERIODIC_TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Attaches the IRQ handler, starts the periodic interrupt at `periodic_hz`
+/// (0 leaves it off), then reads the clock and starts the wall clock. The wall
+/// clock stays at the epoch if the RTC holds no valid date.
+pub fn init(periodic_hz: u32) -> Result<DateTime, RtcError> {
+    if let Ok(Some(register)) = fadt::century_register() {
+        CENTURY_REGISTER.store(register, Ordering::Relaxed);
+    }
+
+    register_irq_handler(RTC_IRQ, "rtc", rtc_irq).map_err(RtcError::Irq)?;
+    set_periodic_rate(periodic_hz)?;
+
+    // E.g. after the CMOS battery ran flat
+    let now = read();
+    if !now.is_valid() {
+        return Err(RtcError::InvalidDate);
+    }
+    sync_wall_clock(&now);
+    Ok(now)
+}
+
+fn sync_wall_clock(now: &DateTime) {
+    BOOT_UNIX_MS.store((now.to_unix() * 1000).saturating_sub(uptime()), Ordering::Relaxed);
+}
+
+/// Seconds since the Unix epoch.
+pub fn wall_clock() -> u64 {
+    (BOOT_UNIX_MS.load(Ordering::Relaxed) + uptime()) / 1000
+}
+
+/// Current date and time according to the wall clock.
+pub fn now() -> DateTime {
+    DateTime::from_unix(wall_clock())
+}
+
+// Raw register values in whatever format the RTC is using
+#[derive(PartialEq, Eq, Clone, Copy)]
+struct RawTime {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: u8,
+}
+
+unsafe fn read_register(register: u8) -> u8 {
+    unsafe {
+        outb(CMOS_ADDRESS, register);
+        inb(CMOS_DATA)
+    }
+}
+
+unsafe fn write_register(register: u8, value: u8) {
+    unsafe {
+        outb(CMOS_ADDRESS, register);
+        outb(CMOS_DATA, value);
+    }
+}
+
+unsafe fn read_raw() -> RawTime {
+    unsafe {
+        // The registers are inconsistent while an update is in progress
+        while read_register(REG_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0 {
+            core::hint::spin_loop();
+        }
+
+        let century = CENTURY_REGISTER.load(Ordering::Relaxed);
+        RawTime {
+            second: read_register(REG_SECONDS),
+            minute: read_register(REG_MINUTES),
+            hour: read_register(REG_HOURS),
+            day: read_register(REG_DAY),
+            month: read_register(REG_MONTH),
+            year: read_register(REG_YEAR),
+            century: if century != 0 { read_register(century) } else { 0 },
+        }
+    }
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+}
+
+fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+/// Reads the date and time from the RTC itself.
+pub fn read() -> DateTime {
+    let _cmos = CMOS.lock();
+
+    let (raw, status_b) = unsafe {
+        // The update may still start between the check and the reads, so read
+        // until two consecutive results agree
+        let mut raw = read_raw();
+        loop {
+            let again = read_raw();
+            if again == raw {
+                break;
+            }
+            raw = again;
+        }
+        (raw, read_register(REG_STATUS_B))
+    };
+
+    let binary = status_b & STATUS_B_BINARY != 0;
+    let decode = |value: u8| if binary { value } else { from_bcd(value) };
+
+    let pm = status_b & STATUS_B_24_HOUR == 0 && raw.hour & HOUR_PM != 0;
+    let mut hour = decode(raw.hour & !HOUR_PM);
+    if status_b & STATUS_B_24_HOUR == 0 {
+        // 12 AM is midnight, 12 PM is noon
+        hour = hour % 12 + if pm { 12 } else { 0 };
+    }
+
+    let century = if raw.century != 0 { decode(raw.century) as u16 } else { 20 };
+    DateTime {
+        year: century * 100 + decode(raw.year) as u16,
+        month: decode(raw.month),
+        day: decode(raw.day),
+        hour,
+        minute: decode(raw.minute),
+        second: decode(raw.second),
+    }
+}
+
+/// Writes `time` back to the RTC and resynchronises the wall clock.
+pub fn set(time: &DateTime) -> Result<(), RtcError> {
+    if !time.is_valid() {
+        return Err(RtcError::InvalidDate);
+    }
+
+    let _cmos = CMOS.lock();
+    unsafe {
+        let status_b = read_register(REG_STATUS_B);
+        let binary = status_b & STATUS_B_BINARY != 0;
+        let encode = |value: u8| if binary { value } else { to_bcd(value) };
+
+        let hour = if status_b & STATUS_B_24_HOUR != 0 {
+            encode(time.hour)
+        } else {
+            let pm = if time.hour >= 12 { HOUR_PM } else { 0 };
+            match time.hour % 12 {
+                0 => encode(12) | pm,
+                hour => encode(hour) | pm,
+            }
+        };
+
+        // Stop the updates while the registers are written
+        write_register(REG_STATUS_B, status_b | STATUS_B_SET);
+        write_register(REG_SECONDS, encode(time.second));
+        write_register(REG_MINUTES, encode(time.minute));
+        write_register(REG_HOURS, hour);
+        write_register(REG_DAY, encode(time.day));
+        write_register(REG_MONTH, encode(time.month));
+        write_register(REG_YEAR, encode((time.year % 100) as u8));
+        let century = CENTURY_REGISTER.load(Ordering::Relaxed);
+        if century != 0 {
+            write_register(century, encode((time.year / 100) as u8));
+        }
+        write_register(REG_STATUS_B, status_b & !STATUS_B_SET);
+    }
+
+    sync_wall_clock(time);
+    Ok(())
+}
+
+/// True if the periodic interrupt can run at `hz`, 0 being off.
+pub fn is_valid_rate(hz: u32) -> bool {
+    hz == 0 || (hz.is_power_of_two() && (2..=8192).contains(&hz))
+}
+
+/// Enables the periodic interrupt at `hz`, or disables it when `hz` is 0.
+pub fn set_periodic_rate(hz: u32) -> Result<(), RtcError> {
+    if !is_valid_rate(hz) {
+        return Err(RtcError::InvalidRate);
+    }
+
+    let _cmos = CMOS.lock();
+    unsafe {
+        let status_b = read_register(REG_STATUS_B);
+        if hz == 0 {
+            write_register(REG_STATUS_B, status_b & !STATUS_B_PERIODIC);
+            return Ok(());
+        }
+
+        // Frequency is 32768 >> (rate - 1)
+        let rate = (RTC_BASE_HZ / hz).trailing_zeros() as u8 + 1;
+        let status_a = read_register(REG_STATUS_A);
+        write_register(REG_STATUS_A, (status_a & !STATUS_A_RATE_MASK) | rate);
+        write_register(REG_STATUS_B, status_b | STATUS_B_PERIODIC);
+
+        // Clear a stale flag, otherwise the RTC never raises the IRQ again
+        read_register(REG_STATUS_C);
+    }
+    Ok(())
+}
+
+/// Periodic interrupts received so far.
+pub fn periodic_ticks() -> u64 {
+    PERIODIC_TICKS.load(Ordering::Relaxed)
+}
+
+fn rtc_irq(_frame: &InterruptFrame) -> IrqReturn {
+    // Reading status C acknowledges the interrupt
+    let status_c = {
+        let _cmos = CMOS.lock();
+        unsafe { read_register(REG_STATUS_C) }
+    };
+
+    if status_c & STATUS_C_ANY == 0 {
+        return IrqReturn::NotHandled;
+    }
+    if status_c & STATUS_C_PERIODIC != 0 {
+        PERIODIC_TICKS.fetch_add(1, Ordering::Relaxed);
+    }
+    IrqReturn::Handled
+}
+
+fn is_leap_year(year: u16) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl DateTime {
+    pub fn is_valid(&self) -> bool {
+        (1970..=2099).contains(&self.year)
+            && (1..=12).contains(&self.month)
+            && self.day >= 1
+            && self.day <= days_in_month(self.year, self.month)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+    }
+
+    /// Seconds since 1970-01-01 00:00:00 UTC.
+    pub fn to_unix(&self) -> u64 {
+        // Days from the civil calendar, with years starting in March so the
+        // leap day is the last one
+        let year = self.year as i64 - if self.month <= 2 { 1 } else { 0 };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let shifted_month = self.month as i64 + if self.month > 2 { -3 } else { 9 };
+        let day_of_year = (153 * shifted_month + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146097 + day_of_era - 719468;
+
+        let seconds = self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64;
+        (days * 86400 + seconds).max(0) as u64
+    }
+
+    pub fn from_unix(timestamp: u64) -> DateTime {
+        let days = (timestamp / 86400) as i64 + 719468;
+        let seconds = timestamp % 86400;
+
+        let era = days.div_euclid(146097);
+        let day_of_era = days - era * 146097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        DateTime {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds / 3600) as u8,
+            minute: (seconds / 60 % 60) as u8,
+            second: (seconds % 60) as u8,
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second)
+    }
+}
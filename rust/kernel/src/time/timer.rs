@@ -0,0 +1,279 @@
+// Kernel software timers.
+//
+// Armed timers sit in a min-heap ordered by deadline. The tick only compares
+// the earliest deadline with the current tick and schedules the timer work:
+// callbacks run from the workqueue, with interrupts enabled.
+
+use core::fmt;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use super::{ms_to_ticks, ticks};
+use crate::sync::IrqSpinLock;
+use crate::workqueue::{self, Work};
+
+const MAX_TIMERS: usize = 32;
+
+pub type TimerFn = fn();
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TimerMode {
+    OneShot,
+    Periodic,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TimerError {
+    /// Every slot of the timer heap is in use
+    TooManyTimers,
+}
+
+/// A software timer, meant to be declared as a `static`, e.g.
+/// `Timer::new(500, blink).periodic().named("blink")`, then started.
+pub struct Timer {
+    name: &'static str,
+    callback: TimerFn,
+    // Delay and mode used by `start`
+    delay_ms: u64,
+    mode: TimerMode,
+    // Period in ticks, 0 for one-shot timers
+    period: AtomicU64,
+    armed: AtomicBool,
+    expirations: AtomicU64,
+}
+
+#[derive(Clone, Copy)]
+struct HeapEntry {
+    deadline: u64,
+    timer: &'static Timer,
+}
+
+struct TimerHeap {
+    entries: [Option<HeapEntry>; MAX_TIMERS],
+    len: usize,
+}
+
+static TIMERS: IrqSpinLock<TimerHeap> =
+    IrqSpinLock::new(TimerHeap { entries: [None; MAX_TIMERS], len: 0 });
+
+// Earliest deadline, read by the tick without taking the lock
+static NEXT_DEADLINE: AtomicU64 = AtomicU64::new(u64::MAX);
+
+static TIMER_WORK: Work = Work::new("timers", run_expired);
+
+impl Timer {
+    /// One-shot timer calling `callback` `deadline_ms` after `start`.
+    pub const fn new(deadline_ms: u64, callback: TimerFn) -> Timer {
+        Timer {
+            name: "timer",
+            callback,
+            delay_ms: deadline_ms,
+            mode: TimerMode::OneShot,
+            period: AtomicU64::new(0),
+            armed: AtomicBool::new(false),
+            expirations: AtomicU64::new(0),
+        }
+    }
+
+    /// Fires every `deadline_ms` once started.
+    pub const fn periodic(mut self) -> Timer {
+        self.mode = TimerMode::Periodic;
+        self
+    }
+
+    /// Name shown by `dump`.
+    pub const fn named(mut self, name: &'static str) -> Timer {
+        self.name = name;
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.armed.load(Ordering::Acquire)
+    }
+
+    /// Arms the timer with the deadline and mode it was built with. Starting an
+    /// armed timer moves its deadline.
+    pub fn start(&'static self) -> Result<(), TimerError> {
+        self.start_with(self.delay_ms, self.mode)
+    }
+
+    /// Fires the timer after `delay_ms`, then every `delay_ms` in periodic
+    /// mode, whatever it was built with.
+    pub fn start_with(&'static self, delay_ms: u64, mode: TimerMode) -> Result<(), TimerError> {
+        let delay = ms_to_ticks(delay_ms).max(1);
+        let period = match mode {
+            TimerMode::OneShot => 0,
+            TimerMode::Periodic => delay,
+        };
+
+        let mut heap = TIMERS.lock();
+        heap.remove(self);
+        self.period.store(period, Ordering::Relaxed);
+        heap.push(HeapEntry { deadline: ticks() + delay, timer: self })?;
+        self.armed.store(true, Ordering::Release);
+        heap.update_next_deadline();
+        Ok(())
+    }
+
+    /// Disarms the timer, returning false if it was not armed. A callback
+    /// already running is not waited for.
+    pub fn cancel(&'static self) -> bool {
+        let mut heap = TIMERS.lock();
+        let removed = heap.remove(self);
+        self.armed.store(false, Ordering::Release);
+        heap.update_next_deadline();
+        removed
+    }
+}
+
+impl TimerHeap {
+    fn deadline(&self, index: usize) -> u64 {
+        self.entries[index].map_or(u64::MAX, |entry| entry.deadline)
+    }
+
+    fn update_next_deadline(&self) {
+        NEXT_DEADLINE.store(self.deadline(0), Ordering::Release);
+    }
+
+    fn push(&mut self, entry: HeapEntry) -> Result<(), TimerError> {
+        if self.len == MAX_TIMERS {
+            return Err(TimerError::TooManyTimers);
+        }
+
+        self.entries[self.len] = Some(entry);
+        self.len += 1;
+        self.sift_up(self.len - 1);
+        Ok(())
+    }
+
+    /// Removes the earliest timer if its deadline is at or before `now`.
+    fn pop_expired(&mut self, now: u64) -> Option<HeapEntry> {
+        if self.len == 0 || self.deadline(0) > now {
+            return None;
+        }
+
+        let entry = self.entries[0];
+        self.remove_at(0);
+        entry
+    }
+
+    fn remove(&mut self, timer: &'static Timer) -> bool {
+        let index = self.entries[..self.len]
+            .iter()
+            .position(|entry| matches!(entry, Some(entry) if ptr::eq(entry.timer, timer)));
+
+        match index {
+            Some(index) => {
+                self.remove_at(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn remove_at(&mut self, index: usize) {
+        self.len -= 1;
+        self.entries.swap(index, self.len);
+        self.entries[self.len] = None;
+
+        if index < self.len {
+            self.sift_down(index);
+            self.sift_up(index);
+        }
+    }
+
+    fn sift_up(&mut self, mut index: usize) {
+        while index > 0 {
+            let parent = (index - 1) / 2;
+            if self.deadline(parent) <= self.deadline(index) {
+                break;
+            }
+            self.entries.swap(parent, index);
+            index = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut index: usize) {
+        loop {
+            let mut smallest = index;
+            for child in [2 * index + 1, 2 * index + 2] {
+                if child < self.len && self.deadline(child) < self.deadline(smallest) {
+                    smallest = child;
+                }
+            }
+            if smallest == index {
+                break;
+            }
+            self.entries.swap(smallest, index);
+            index = smallest;
+        }
+    }
+}
+
+/// Tick of the earliest deadline, `u64::MAX` when no timer is armed.
+pub(super) fn next_deadline() -> u64 {
+    NEXT_DEADLINE.load(Ordering::Acquire)
+}
+
+/// Tick hook: wakes the timer work once the earliest deadline passed.
+pub(super) fn tick(now: u64) {
+    if now >= NEXT_DEADLINE.load(Ordering::Acquire) {
+        workqueue::schedule(&TIMER_WORK);
+    }
+}
+
+// Bottom half, runs the callbacks of the expired timers
+fn run_expired() {
+    let now = ticks();
+
+    loop {
+        let timer = {
+            let mut heap = TIMERS.lock();
+            let Some(entry) = heap.pop_expired(now) else {
+                break;
+            };
+
+            // Periodic timers are re-armed before the callback so it may cancel
+            // them. Missed periods are skipped rather than fired in a burst.
+            let timer = entry.timer;
+            let period = timer.period.load(Ordering::Relaxed);
+            if period == 0 {
+                timer.armed.store(false, Ordering::Release);
+            } else {
+                let mut deadline = entry.deadline + period;
+                if deadline <= now {
+                    deadline = now + period;
+                }
+                // A slot was just freed, this cannot fail
+                let _ = heap.push(HeapEntry { deadline, timer });
+            }
+            heap.update_next_deadline();
+            timer
+        };
+
+        timer.expirations.fetch_add(1, Ordering::Relaxed);
+        (timer.callback)();
+    }
+}
+
+/// Prints the armed timers in heap order.
+pub fn dump(w: &mut impl fmt::Write) -> fmt::Result {
+    let now = ticks();
+    // Copy the heap so the lock is not held while printing
+    let entries = TIMERS.lock().entries;
+
+    writeln!(w, "{:<16} {:>10} {:>8} {:>8}", "timer", "in ticks", "period", "fired")?;
+    for entry in entries.iter().flatten() {
+        writeln!(w, "{:<16} {:>10} {:>8} {:>8}",
+            entry.timer.name,
+            entry.deadline.saturating_sub(now),
+            entry.timer.period.load(Ordering::Relaxed),
+            entry.timer.expirations.load(Ordering::Relaxed),
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,109 @@
+// Time Stamp Counter calibration.
+//
+// The TSC frequency is measured against the HPET, or PIT channel 2 when there
+// is none, at boot. Only an invariant TSC, which ticks at a constant rate
+// whatever the power state, is trusted as a clock source.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use super::hpet;
+use super::pit::{self, PIT_FREQUENCY};
+use crate::cpu::{cpuid, rdtsc};
+use crate::interrupts::without_interrupts;
+
+// CPUID 0x80000007 EDX
+const CPUID_INVARIANT_TSC: u32 = 1 << 8;
+
+// Each calibration run lasts 10 ms, the shortest one wins since SMIs and
+// emulation hiccups can only make a run longer
+const CALIBRATION_NS: u64 = 10_000_000;
+const CALIBRATION_COUNTS: u16 = (PIT_FREQUENCY / 100) as u16;
+const CALIBRATION_RUNS: usize = 5;
+
+// Runs further apart than this (in parts per thousand) mean the TSC is unstable
+const MAX_SPREAD_PERMILLE: u64 = 5;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TscError {
+    /// The TSC keeps counting at a constant rate only if it is invariant
+    NotInvariant,
+    /// The calibration runs disagree too much
+    Unstable,
+}
+
+static FREQUENCY_HZ: AtomicU64 = AtomicU64::new(0);
+// Nanoseconds per cycle as a 32.32 fixed point number
+static NS_MULT: AtomicU64 = AtomicU64::new(0);
+// Counter value when the TSC became the clock
+static BASE: AtomicU64 = AtomicU64::new(0);
+// Calibrated and invariant
+static RELIABLE: AtomicBool = AtomicBool::new(false);
+
+pub fn is_invariant() -> bool {
+    cpuid(0x8000_0000).eax >= 0x8000_0007 && cpuid(0x8000_0007).edx & CPUID_INVARIANT_TSC != 0
+}
+
+/// Measures the TSC frequency. The frequency is kept even when the TSC is
+/// not invariant, cycle counts stay meaningful over short periods.
+pub fn calibrate() -> Result<u64, TscError> {
+    let use_hpet = hpet::is_available();
+    let hpet_ticks = if use_hpet { hpet::ns_to_ticks(CALIBRATION_NS) } else { 0 };
+
+    let mut min = u64::MAX;
+    let mut max = 0;
+    for _ in 0..CALIBRATION_RUNS {
+        let cycles = if use_hpet {
+            without_interrupts(|| hpet::measure_tsc(hpet_ticks))
+        } else {
+            pit::run_channel2(CALIBRATION_COUNTS)
+        };
+        min = min.min(cycles);
+        max = max.max(cycles);
+    }
+
+    if min == 0 || (max - min) * 1000 / min > MAX_SPREAD_PERMILLE {
+        return Err(TscError::Unstable);
+    }
+
+    let hz = if use_hpet {
+        (min as u128 * 1_000_000_000 / hpet::ticks_to_ns(hpet_ticks) as u128) as u64
+    } else {
+        min * PIT_FREQUENCY as u64 / CALIBRATION_COUNTS as u64
+    };
+    set_frequency(hz);
+
+    if !is_invariant() {
+        return Err(TscError::NotInvariant);
+    }
+    RELIABLE.store(true, Ordering::Release);
+    Ok(hz)
+}
+
+fn set_frequency(hz: u64) {
+    FREQUENCY_HZ.store(hz, Ordering::Relaxed);
+    NS_MULT.store(((1_000_000_000u128 << 32) / hz as u128) as u64, Ordering::Relaxed);
+}
+
+/// Makes the calibrated TSC the clock, starting from now.
+pub(super) fn start_clock() {
+    BASE.store(rdtsc(), Ordering::Relaxed);
+}
+
+/// Calibrated frequency in Hz, 0 before calibration.
+pub fn frequency() -> u64 {
+    FREQUENCY_HZ.load(Ordering::Relaxed)
+}
+
+/// True once the TSC is calibrated and can be trusted as a clock.
+pub fn is_reliable() -> bool {
+    RELIABLE.load(Ordering::Acquire)
+}
+
+pub fn cycles_to_ns(cycles: u64) -> u64 {
+    ((cycles as u128 * NS_MULT.load(Ordering::Relaxed) as u128) >> 32) as u64
+}
+
+/// Nanoseconds since `start_clock`.
+pub fn now_ns() -> u64 {
+    cycles_to_ns(rdtsc().saturating_sub(BASE.load(Ordering::Relaxed)))
+}
@@ -0,0 +1,123 @@
+// Fault tolerant memory access.
+//
+// These helpers copy memory through instructions registered in the exception
+// fixup table, so an unmapped or non-canonical address produces an error
+// instead of a kernel crash.
+
+use core::arch::asm;
+use core::mem::{size_of, MaybeUninit};
+
+// End of the lower canonical half, where user space lives
+const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum AccessError {
+    /// The range is outside user space or wraps around.
+    BadAddress,
+    /// A fault was taken during the copy, `not_copied` bytes were left.
+    Fault { not_copied: usize },
+}
+
+/// Plain data that can be read from any address, like integers.
+///
+/// # Safety
+/// Every bit pattern must be a valid value of the type.
+pub unsafe trait Pod: Copy {}
+
+macro_rules! impl_pod {
+    ($($ty:ty),*) => {
+        $(unsafe impl Pod for $ty {})*
+    };
+}
+
+impl_pod!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+fn access_ok(addr: u64, len: usize) -> bool {
+    match addr.checked_add(len as u64) {
+        Some(end) => end <= USER_SPACE_END,
+        None => false,
+    }
+}
+
+/// Copies `len` bytes and returns how many could not be copied because of a fault.
+///
+/// `rep movsb` is restartable: on a fault RCX holds the remaining byte count,
+/// and that is what the fixup returns.
+unsafe fn copy_unchecked(dst: *mut u8, src: *const u8, len: usize) -> usize {
+    let remaining: usize;
+    unsafe {
+        asm!(
+            "2: rep movsb",
+            "3:",
+            ".pushsection __ex_table, \"a\"",
+            ".balign 8",
+            ".quad 2b, 3b",
+            ".popsection",
+            inout("rcx") len => remaining,
+            inout("rdi") dst => _,
+            inout("rsi") src => _,
+            options(nostack, preserves_flags),
+        );
+    }
+    remaining
+}
+
+fn copy_result(not_copied: usize) -> Result<(), AccessError> {
+    if not_copied == 0 {
+        Ok(())
+    } else {
+        Err(AccessError::Fault { not_copied })
+    }
+}
+
+/// Copies `dst.len()` bytes from the user pointer `src` into `dst`.
+///
+/// # Safety
+/// `src` must belong to the current address space: the range is only checked
+/// to be in user space, a fault is the only other error caught.
+pub unsafe fn copy_from_user(dst: &mut [u8], src: *const u8) -> Result<(), AccessError> {
+    if !access_ok(src as u64, dst.len()) {
+        return Err(AccessError::BadAddress);
+    }
+    copy_result(unsafe { copy_unchecked(dst.as_mut_ptr(), src, dst.len()) })
+}
+
+/// Copies `src` to the user pointer `dst`.
+///
+/// # Safety
+/// `dst` must belong to the current address space and to memory the caller
+/// may overwrite, mapped memory is written whatever it holds.
+pub unsafe fn copy_to_user(dst: *mut u8, src: &[u8]) -> Result<(), AccessError> {
+    if !access_ok(dst as u64, src.len()) {
+        return Err(AccessError::BadAddress);
+    }
+    copy_result(unsafe { copy_unchecked(dst, src.as_ptr(), src.len()) })
+}
+
+/// Reads a value from any address, kernel or user, without crashing if it is
+/// not mapped. Alignment is not required.
+pub fn probe_read<T: Pod>(src: *const T) -> Result<T, AccessError> {
+    let mut value = MaybeUninit::<T>::uninit();
+    let not_copied = unsafe {
+        copy_unchecked(value.as_mut_ptr() as *mut u8, src as *const u8, size_of::<T>())
+    };
+
+    copy_result(not_copied)?;
+    Ok(unsafe { value.assume_init() })
+}
+
+/// Writes a value to any address, kernel or user, without crashing if it is
+/// not mapped. Alignment is not required.
+///
+/// # Safety
+/// Nothing stops the write when `dst` is mapped: it must not overlap memory
+/// that the rest of the kernel relies on, unless that's the point, like a
+/// breakpoint patched into code.
+pub unsafe fn probe_write<T: Pod>(dst: *mut T, value: T) -> Result<(), AccessError> {
+    let not_copied = unsafe {
+        copy_unchecked(dst as *mut u8, &value as *const T as *const u8, size_of::<T>())
+    };
+    copy_result(not_copied)
+}
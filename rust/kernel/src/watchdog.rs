@@ -0,0 +1,160 @@
+// Lockup detectors.
+//
+// Soft lockup: the timer tick checks that the idle loop, the only scheduling
+// point of the kernel, keeps touching the watchdog. Code spinning with
+// interrupts enabled stops it from doing so, and after `threshold` seconds the
+// interrupted frame and a backtrace are dumped.
+//
+// Hard lockup: with interrupts disabled the timer IRQ never arrives. When the
+// CPU has a performance monitoring unit, counter 0 is programmed to raise an
+// NMI roughly every second, which checks that timer ticks keep coming.
+
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+use crate::backtrace::write_backtrace;
+use crate::cpu::{cpuid, rdmsr, wrmsr};
+use crate::display::force_writer;
+use crate::interrupts::{apic, InterruptFrame};
+use crate::time;
+
+pub const DEFAULT_THRESHOLD_SECS: u64 = 10;
+
+// Architectural performance monitoring MSRs
+const IA32_PMC0: u32 = 0xC1;
+const IA32_PERFEVTSEL0: u32 = 0x186;
+// PMU version 2 and later
+const IA32_PERF_GLOBAL_STATUS: u32 = 0x38E;
+const IA32_PERF_GLOBAL_OVF_CTRL: u32 = 0x390;
+const PMC0_OVERFLOW: u64 = 1 << 0;
+// Unhalted core cycles, counted in ring 0 and 3, interrupt on overflow
+const EVENT_UNHALTED_CYCLES: u64 = 0x3C | 1 << 16 | 1 << 17 | 1 << 20 | 1 << 22;
+// Cycles between two NMIs, about a second on a 2 GHz CPU
+const NMI_PERIOD_CYCLES: u64 = 0x7FFF_FFFF;
+
+static THRESHOLD_SECS: AtomicU64 = AtomicU64::new(DEFAULT_THRESHOLD_SECS);
+
+// Tick of the last heartbeat
+static TOUCHED_AT: AtomicU64 = AtomicU64::new(0);
+// Set once a lockup was reported, cleared by the next heartbeat
+static REPORTED: AtomicBool = AtomicBool::new(false);
+static SOFT_LOCKUP_ENABLED: AtomicBool = AtomicBool::new(false);
+
+static HARD_LOCKUP_ENABLED: AtomicBool = AtomicBool::new(false);
+static LAST_NMI_TICKS: AtomicU64 = AtomicU64::new(0);
+static STALLED_NMIS: AtomicU64 = AtomicU64::new(0);
+// Set when the PMU has the global overflow status register
+static HAS_GLOBAL_STATUS: AtomicBool = AtomicBool::new(false);
+static COUNTER_WIDTH: AtomicU32 = AtomicU32::new(40);
+
+/// Starts the soft-lockup detector, driven by the timer tick.
+pub fn init(threshold_secs: u64) {
+    set_threshold(threshold_secs);
+    touch();
+    SOFT_LOCKUP_ENABLED.store(true, Ordering::Release);
+}
+
+pub fn set_threshold(secs: u64) {
+    THRESHOLD_SECS.store(secs.max(1), Ordering::Relaxed);
+}
+
+/// Heartbeat, called from scheduling points.
+pub fn touch() {
+    TOUCHED_AT.store(time::ticks(), Ordering::Relaxed);
+    REPORTED.store(false, Ordering::Relaxed);
+}
+
+/// Timer tick hook, `frame` is the interrupted context.
+pub fn tick(frame: &InterruptFrame) {
+    if !SOFT_LOCKUP_ENABLED.load(Ordering::Acquire) {
+        return;
+    }
+
+    let tick_hz = time::tick_hz().max(1);
+    let limit = THRESHOLD_SECS.load(Ordering::Relaxed) * tick_hz;
+    let stuck = time::ticks().saturating_sub(TOUCHED_AT.load(Ordering::Relaxed));
+
+    if stuck >= limit && !REPORTED.swap(true, Ordering::Relaxed) {
+        let secs = stuck / tick_hz;
+        report(frame, format_args!("soft lockup - CPU stuck for {}s", secs));
+    }
+}
+
+fn report(frame: &InterruptFrame, reason: core::fmt::Arguments) {
+    if let Some(mut writer) = force_writer() {
+        let _ = writeln!(writer, "\nBUG: {}", reason);
+        let _ = writeln!(writer, "{}", frame);
+        let _ = write_backtrace(&mut *writer, frame.rip, frame.rbp);
+    }
+}
+
+/// Starts the NMI based hard-lockup detector. Needs the local APIC and an
+/// architectural PMU, returns false if they are missing.
+pub fn enable_hard_lockup_detector() -> bool {
+    // CPUID leaf 0xA: PMU version in EAX[7:0], general purpose counters in
+    // EAX[15:8] and their width in EAX[23:16]
+    let max_leaf = cpuid(0).eax;
+    if !apic::is_enabled() || max_leaf < 0xA {
+        return false;
+    }
+    let pmu = cpuid(0xA).eax;
+    if pmu & 0xFF == 0 || (pmu >> 8) & 0xFF == 0 {
+        return false;
+    }
+    HAS_GLOBAL_STATUS.store(pmu & 0xFF >= 2, Ordering::Relaxed);
+    COUNTER_WIDTH.store((pmu >> 16) & 0xFF, Ordering::Relaxed);
+
+    LAST_NMI_TICKS.store(time::ticks(), Ordering::Relaxed);
+    apic::set_perf_counter_nmi();
+    unsafe {
+        wrmsr(IA32_PERFEVTSEL0, 0);
+        rearm_counter();
+        wrmsr(IA32_PERFEVTSEL0, EVENT_UNHALTED_CYCLES);
+    }
+    HARD_LOCKUP_ENABLED.store(true, Ordering::Release);
+    true
+}
+
+unsafe fn rearm_counter() {
+    // Counts up and raises the NMI on overflow, only the low 32 bits can be written
+    unsafe { wrmsr(IA32_PMC0, (NMI_PERIOD_CYCLES.wrapping_neg()) & 0xFFFF_FFFF) };
+}
+
+// Checks and acknowledges the overflow of counter 0
+fn counter_overflowed() -> bool {
+    if HAS_GLOBAL_STATUS.load(Ordering::Relaxed) {
+        if unsafe { rdmsr(IA32_PERF_GLOBAL_STATUS) } & PMC0_OVERFLOW == 0 {
+            return false;
+        }
+        unsafe { wrmsr(IA32_PERF_GLOBAL_OVF_CTRL, PMC0_OVERFLOW) };
+        return true;
+    }
+
+    // No status register: an armed counter has its top bit set until it wraps
+    let top_bit = 1u64 << (COUNTER_WIDTH.load(Ordering::Relaxed).clamp(32, 64) - 1);
+    let count = unsafe { rdmsr(IA32_PMC0) };
+    count & top_bit == 0
+}
+
+/// NMI hook. Returns true if the NMI came from the watchdog counter.
+pub fn handle_nmi(frame: &InterruptFrame) -> bool {
+    if !HARD_LOCKUP_ENABLED.load(Ordering::Acquire) || !counter_overflowed() {
+        return false;
+    }
+
+    unsafe { rearm_counter() };
+    // The LVT entry masks itself when the NMI is delivered
+    apic::set_perf_counter_nmi();
+
+    let ticks = time::ticks();
+    if ticks != LAST_NMI_TICKS.swap(ticks, Ordering::Relaxed) {
+        STALLED_NMIS.store(0, Ordering::Relaxed);
+        return true;
+    }
+
+    let stalled = STALLED_NMIS.fetch_add(1, Ordering::Relaxed) + 1;
+    if stalled == THRESHOLD_SECS.load(Ordering::Relaxed) {
+        report(frame, format_args!("hard lockup - no timer interrupt for ~{}s", stalled));
+    }
+    true
+}
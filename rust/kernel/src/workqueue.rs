@@ -0,0 +1,186 @@
+// Deferred interrupt work.
+//
+// An IRQ handler (top half) only acknowledges its device, stashes whatever it
+// read and schedules a `Work` item. Items run later from the idle loop with
+// interrupts enabled (bottom half).
+
+use core::fmt;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering};
+
+use crate::cpu::rdtsc;
+use crate::interrupts::without_interrupts;
+use crate::ring::RingBuffer;
+
+const QUEUE_SIZE: usize = 32;
+const MAX_WORK_ITEMS: usize = 16;
+
+pub type WorkFn = fn();
+
+/// A unit of deferred work, meant to be declared as a `static`.
+///
+/// Scheduling an item that is already pending does nothing: the function runs
+/// once and must handle everything that accumulated meanwhile.
+pub struct Work {
+    name: &'static str,
+    func: WorkFn,
+    pending: AtomicBool,
+    queued_at: AtomicU64,
+    stats: WorkStats,
+}
+
+struct WorkStats {
+    scheduled: AtomicU64,
+    coalesced: AtomicU64,
+    dropped: AtomicU64,
+    runs: AtomicU64,
+    total_cycles: AtomicU64,
+    max_cycles: AtomicU64,
+    max_latency_cycles: AtomicU64,
+}
+
+/// Snapshot of the statistics of a work item. Times are in TSC cycles.
+#[derive(Clone, Copy, Debug)]
+pub struct WorkStatsSnapshot {
+    pub name: &'static str,
+    pub scheduled: u64,
+    pub coalesced: u64,
+    pub dropped: u64,
+    pub runs: u64,
+    pub avg_cycles: u64,
+    pub max_cycles: u64,
+    pub max_latency_cycles: u64,
+}
+
+impl Work {
+    pub const fn new(name: &'static str, func: WorkFn) -> Work {
+        Work {
+            name,
+            func,
+            pending: AtomicBool::new(false),
+            queued_at: AtomicU64::new(0),
+            stats: WorkStats {
+                scheduled: AtomicU64::new(0),
+                coalesced: AtomicU64::new(0),
+                dropped: AtomicU64::new(0),
+                runs: AtomicU64::new(0),
+                total_cycles: AtomicU64::new(0),
+                max_cycles: AtomicU64::new(0),
+                max_latency_cycles: AtomicU64::new(0),
+            },
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending.load(Ordering::Acquire)
+    }
+
+    pub fn stats(&self) -> WorkStatsSnapshot {
+        let stats = &self.stats;
+        let runs = stats.runs.load(Ordering::Relaxed);
+        WorkStatsSnapshot {
+            name: self.name,
+            scheduled: stats.scheduled.load(Ordering::Relaxed),
+            coalesced: stats.coalesced.load(Ordering::Relaxed),
+            dropped: stats.dropped.load(Ordering::Relaxed),
+            runs,
+            avg_cycles: stats.total_cycles.load(Ordering::Relaxed).checked_div(runs).unwrap_or(0),
+            max_cycles: stats.max_cycles.load(Ordering::Relaxed),
+            max_latency_cycles: stats.max_latency_cycles.load(Ordering::Relaxed),
+        }
+    }
+}
+
+static QUEUE: RingBuffer<&'static Work, QUEUE_SIZE> = RingBuffer::new();
+
+// Every item ever scheduled, for statistics
+static KNOWN_WORK: [AtomicPtr<Work>; MAX_WORK_ITEMS] =
+    [const { AtomicPtr::new(ptr::null_mut()) }; MAX_WORK_ITEMS];
+
+/// Queues `work` to run from the idle loop. Safe to call from IRQ context.
+///
+/// Returns false if the item was already pending or the queue is full.
+pub fn schedule(work: &'static Work) -> bool {
+    work.stats.scheduled.fetch_add(1, Ordering::Relaxed);
+
+    if work.pending.swap(true, Ordering::AcqRel) {
+        work.stats.coalesced.fetch_add(1, Ordering::Relaxed);
+        return false;
+    }
+
+    remember(work);
+    work.queued_at.store(rdtsc(), Ordering::Relaxed);
+
+    // The queue has a single producer: keep IRQ handlers out while pushing
+    let queued = without_interrupts(|| QUEUE.push(work).is_ok());
+    if !queued {
+        work.pending.store(false, Ordering::Release);
+        work.stats.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+    queued
+}
+
+/// Runs every queued item with interrupts enabled, including those queued
+/// while running. Must not be called from IRQ context.
+pub fn run_pending() {
+    while let Some(work) = QUEUE.pop() {
+        let start = rdtsc();
+
+        // Clear first, so an interrupt arriving while the item runs queues it again
+        work.pending.store(false, Ordering::Release);
+        (work.func)();
+
+        let end = rdtsc();
+        let stats = &work.stats;
+        let latency = start.saturating_sub(work.queued_at.load(Ordering::Relaxed));
+        stats.runs.fetch_add(1, Ordering::Relaxed);
+        stats.total_cycles.fetch_add(end - start, Ordering::Relaxed);
+        stats.max_cycles.fetch_max(end - start, Ordering::Relaxed);
+        stats.max_latency_cycles.fetch_max(latency, Ordering::Relaxed);
+    }
+}
+
+pub fn has_pending() -> bool {
+    !QUEUE.is_empty()
+}
+
+fn remember(work: &'static Work) {
+    let work = work as *const Work as *mut Work;
+    for slot in KNOWN_WORK.iter() {
+        match slot.compare_exchange(ptr::null_mut(), work, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => return,
+            Err(current) if current == work => return,
+            Err(_) => {}
+        }
+    }
+}
+
+/// Calls `f` with the statistics of every work item scheduled so far.
+pub fn for_each_stats(mut f: impl FnMut(WorkStatsSnapshot)) {
+    for slot in KNOWN_WORK.iter() {
+        let work = slot.load(Ordering::Acquire);
+        if work.is_null() {
+            break;
+        }
+        f(unsafe { &*work }.stats());
+    }
+}
+
+/// Prints a table with the statistics of every work item.
+pub fn dump(w: &mut impl fmt::Write) -> fmt::Result {
+    writeln!(w, "{:<12} {:>8} {:>8} {:>8} {:>10} {:>10} {:>10}",
+        "work", "runs", "merged", "dropped", "avg cyc", "max cyc", "max lat")?;
+
+    let mut result = Ok(());
+    for_each_stats(|s| {
+        if result.is_ok() {
+            result = writeln!(w, "{:<12} {:>8} {:>8} {:>8} {:>10} {:>10} {:>10}",
+                s.name, s.runs, s.coalesced, s.dropped, s.avg_cycles, s.max_cycles, s.max_latency_cycles);
+        }
+    });
+    result
+}